@@ -0,0 +1,68 @@
+// Deterministic synthetic programs for the benchmarks, so results can be
+// compared between runs and machines.
+
+// Identifier-heavy source where every other word is a keyword.
+pub fn keywords() -> String {
+    let words = [
+        "and", "alpha", "class", "beta", "else", "gamma", "false", "delta", "fun", "epsilon",
+        "for", "zeta", "if", "eta", "nil", "theta", "or", "iota", "print", "kappa", "return",
+        "lambda", "super", "mu", "this", "nu", "true", "xi", "var", "omicron", "while", "pi",
+    ];
+    let mut source = String::new();
+    for i in 0..100_000 {
+        source.push_str(words[i % words.len()]);
+        source.push(if i % 16 == 15 { '\n' } else { ' ' });
+    }
+    source
+}
+
+// String literals of varying length, some with escapes and non-ASCII text.
+pub fn strings() -> String {
+    let mut source = String::new();
+    for i in 0..50_000 {
+        let text = match i % 4 {
+            0 => "short",
+            1 => "a somewhat longer string literal with spaces",
+            2 => "escapes\\tand\\nnewlines \\u{e9}",
+            _ => "unicode: héllo wörld 中文",
+        };
+        source.push_str(&format!("var s{i} = \"{text} {i}\";\n"));
+    }
+    source
+}
+
+// Mostly comments, with a statement every few lines.
+pub fn comments() -> String {
+    let mut source = String::new();
+    for i in 0..50_000 {
+        match i % 5 {
+            0 => source.push_str(&format!("var x{i} = {i};\n")),
+            4 => source.push_str("/* a block comment\n   spanning lines /* nested */ */\n"),
+            _ => source.push_str("// a line comment explaining the surrounding code\n"),
+        }
+    }
+    source
+}
+
+// A single 100k-character line of dense expressions.
+pub fn one_line() -> String {
+    let mut source = String::new();
+    let mut i = 0;
+    while source.len() < 100_000 {
+        source.push_str(&format!("(a{i}+{i}.5)*b>=c!=d;"));
+        i += 1;
+    }
+    source
+}
+
+// A large program of about 2 MB mixing every kind of token.
+pub fn program() -> String {
+    let mut source = String::new();
+    for i in 0..20_000 {
+        source.push_str(&format!(
+            "// function number {i}\n\
+             fun f{i}(a, b) {{\n    var s = \"value {i}\";\n    if (a >= {i}.5) return a * b;\n    return s;\n}}\n"
+        ));
+    }
+    source
+}
@@ -0,0 +1,80 @@
+mod corpus;
+
+use craftinginterpreters::{Scanner, ScannerOptions, TokenType};
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    io::{BufReader, Cursor},
+    time::Instant,
+};
+
+const ITERATIONS: u32 = 10;
+
+fn bench(name: &str, source: &str) {
+    report(name, source, |source| {
+        let scanned = Scanner::new(source).scan_tokens();
+        scanned.map_or(0, |tokens| tokens.len())
+    });
+}
+
+fn bench_streamed(name: &str, source: &str) {
+    report(name, source, |source| {
+        let reader = BufReader::new(Cursor::new(source.as_bytes()));
+        let scanned = Scanner::from_reader(reader, ScannerOptions::default()).scan_tokens();
+        scanned.map_or(0, |tokens| tokens.len())
+    });
+}
+
+fn report(name: &str, source: &str, scan: impl Fn(&str) -> usize) {
+    let mut tokens = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        tokens += scan(black_box(source));
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{name:>10}: {:>8} bytes {:>8} tokens {:>12.0} tokens/s {:>7.1} MB/s",
+        source.len(),
+        tokens / ITERATIONS as usize,
+        tokens as f64 / seconds,
+        (source.len() * ITERATIONS as usize) as f64 / seconds / 1e6
+    );
+}
+
+// Times keyword lookups alone, for every word of an identifier-heavy source.
+fn bench_lookup(name: &str, words: &[&str], lookup: impl Fn(&str) -> Option<TokenType>) {
+    let mut keywords = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for word in words {
+            keywords += lookup(black_box(word)).is_some() as usize;
+        }
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{name:>10}: {:>8} words {:>8} keywords {:>12.0} lookups/s",
+        words.len(),
+        keywords / ITERATIONS as usize,
+        (words.len() * ITERATIONS as usize) as f64 / seconds
+    );
+}
+
+fn main() {
+    bench("keywords", &corpus::keywords());
+    bench("strings", &corpus::strings());
+    bench("comments", &corpus::comments());
+    bench("one line", &corpus::one_line());
+    let program = corpus::program();
+    bench("program", &program);
+    bench_streamed("streamed", &program);
+
+    // to_keyword matches on the length, against hashing as it used to
+    let source = corpus::keywords();
+    let words: Vec<_> = source
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+        .collect();
+    let table: HashMap<_, _> = TokenType::keywords().iter().copied().collect();
+    bench_lookup("hashed", &words, |word| table.get(word).copied());
+    bench_lookup("matched", &words, TokenType::to_keyword);
+}
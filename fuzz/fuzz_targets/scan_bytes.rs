@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/support/check.rs"]
+mod check;
+
+// arbitrary bytes, with invalid UTF-8 replaced
+fuzz_target!(|data: &[u8]| {
+    check::scan(&String::from_utf8_lossy(data));
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/support/check.rs"]
+mod check;
+
+// valid UTF-8 built by arbitrary, which reaches non-ASCII text more often
+fuzz_target!(|source: &str| {
+    check::scan(source);
+});
@@ -0,0 +1,443 @@
+use crate::Token;
+use std::{borrow::Cow, ops::Range};
+
+// The syntax tree the parser builds from tokens. Nodes hold the tokens they
+// came from, so later passes can still say where in the source they are, and
+// each expression and statement the span of source it was parsed from.
+pub type Expr<'a> = Spanned<ExprKind<'a>>;
+pub type Stmt<'a> = Spanned<StmtKind<'a>>;
+
+// A node with the byte offsets of its first and last tokens, from the start
+// of the one to the end of the other, so `&source[span]` is its whole text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Range<usize>,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Range<usize>) -> Self {
+        Spanned { node, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind<'a> {
+    // `[a, b, c]`
+    ArrayLiteral(Vec<Expr<'a>>),
+    Assign {
+        name: Token<'a>,
+        value: Box<Expr<'a>>,
+    },
+    // the comma operator too, as a COMMA operator
+    Binary {
+        left: Box<Expr<'a>>,
+        operator: Token<'a>,
+        right: Box<Expr<'a>>,
+    },
+    // `paren` is the closing parenthesis, for errors in the call to point at
+    Call {
+        callee: Box<Expr<'a>>,
+        paren: Token<'a>,
+        arguments: Vec<Expr<'a>>,
+    },
+    // `object.name`
+    Get {
+        object: Box<Expr<'a>>,
+        name: Token<'a>,
+    },
+    Grouping(Box<Expr<'a>>),
+    // `object[index]`, where `bracket` is the closing bracket
+    Index {
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+    },
+    // `object[index] = value`
+    IndexSet {
+        object: Box<Expr<'a>>,
+        bracket: Token<'a>,
+        index: Box<Expr<'a>>,
+        value: Box<Expr<'a>>,
+    },
+    // `"a ${x} b"`: the pieces of string and the expressions between them
+    // in turn, starting and ending with a piece, so pieces are the Str
+    // literals at even indices and expressions are at odd ones
+    Interpolation(Vec<Expr<'a>>),
+    // `fun (params) { body }`, a function without a name
+    Lambda {
+        keyword: Token<'a>,
+        params: Vec<Token<'a>>,
+        body: Vec<Stmt<'a>>,
+    },
+    Literal(LiteralValue<'a>),
+    // `{key: value, ...}`, pairs in source order
+    MapLiteral(Vec<(Expr<'a>, Expr<'a>)>),
+    // `and` and `or`, apart from Binary since they don't always evaluate
+    // their right operand
+    Logical {
+        left: Box<Expr<'a>>,
+        operator: Token<'a>,
+        right: Box<Expr<'a>>,
+    },
+    // `condition ? then_expr : else_expr`
+    Ternary {
+        condition: Box<Expr<'a>>,
+        then_expr: Box<Expr<'a>>,
+        else_expr: Box<Expr<'a>>,
+    },
+    // `object.name = value`
+    Set {
+        object: Box<Expr<'a>>,
+        name: Token<'a>,
+        value: Box<Expr<'a>>,
+    },
+    // `super.method`
+    Super {
+        keyword: Token<'a>,
+        method: Token<'a>,
+    },
+    This(Token<'a>),
+    Unary {
+        operator: Token<'a>,
+        right: Box<Expr<'a>>,
+    },
+    Variable(Token<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind<'a> {
+    Block(Vec<Stmt<'a>>),
+    // the keyword, for errors to point at
+    Break(Token<'a>),
+    // the superclass is an ExprKind::Variable
+    Class {
+        name: Token<'a>,
+        superclass: Option<Expr<'a>>,
+        methods: Vec<Function<'a>>,
+    },
+    Continue(Token<'a>),
+    Expression(Expr<'a>),
+    Function(Function<'a>),
+    If {
+        condition: Expr<'a>,
+        then_branch: Box<Stmt<'a>>,
+        else_branch: Option<Box<Stmt<'a>>>,
+    },
+    Print(Expr<'a>),
+    // the keyword is kept for errors about where the return is
+    Return {
+        keyword: Token<'a>,
+        value: Option<Expr<'a>>,
+    },
+    Var {
+        name: Token<'a>,
+        initializer: Option<Expr<'a>>,
+    },
+    // `increment` is the third clause of a for loop, which runs after the
+    // body even when a continue cuts the body short
+    While {
+        condition: Expr<'a>,
+        body: Box<Stmt<'a>>,
+        increment: Option<Expr<'a>>,
+        syntax: LoopSyntax,
+    },
+}
+
+// What a While was parsed from. A `for` is desugared into one, inside a
+// block that runs the initializer first if the `for` has one; this says so
+// apart from a block written that way, so the loop can be printed as written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopSyntax {
+    While,
+    For { initializer: bool },
+}
+
+// a named function, declared on its own or as a method of a class
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function<'a> {
+    pub name: Token<'a>,
+    pub params: Vec<Token<'a>>,
+    pub body: Vec<Stmt<'a>>,
+    // a method declared `class name() { ... }`, called on the class itself
+    // rather than on its instances
+    pub is_static: bool,
+    // a method declared without a parameter list, `name { ... }`, run when
+    // the property is read
+    pub is_getter: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue<'a> {
+    Number(f64),
+    Str(Cow<'a, str>),
+    Bool(bool),
+    Nil,
+}
+
+// The constructors span a node from its first child or token to its last,
+// where those are its ends; the parser spans the rest itself.
+impl<'a> Expr<'a> {
+    pub fn assign(name: Token<'a>, value: Expr<'a>) -> Self {
+        let span = name.span.start..value.span.end;
+        let node = ExprKind::Assign {
+            name,
+            value: Box::new(value),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn binary(left: Expr<'a>, operator: Token<'a>, right: Expr<'a>) -> Self {
+        let span = left.span.start..right.span.end;
+        let node = ExprKind::Binary {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn call(callee: Expr<'a>, paren: Token<'a>, arguments: Vec<Expr<'a>>) -> Self {
+        let span = callee.span.start..paren.span.end;
+        let node = ExprKind::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn get(object: Expr<'a>, name: Token<'a>) -> Self {
+        let span = object.span.start..name.span.end;
+        let node = ExprKind::Get {
+            object: Box::new(object),
+            name,
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn index(object: Expr<'a>, bracket: Token<'a>, index: Expr<'a>) -> Self {
+        let span = object.span.start..bracket.span.end;
+        let node = ExprKind::Index {
+            object: Box::new(object),
+            bracket,
+            index: Box::new(index),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn index_set(
+        object: Expr<'a>,
+        bracket: Token<'a>,
+        index: Expr<'a>,
+        value: Expr<'a>,
+    ) -> Self {
+        let span = object.span.start..value.span.end;
+        let node = ExprKind::IndexSet {
+            object: Box::new(object),
+            bracket,
+            index: Box::new(index),
+            value: Box::new(value),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn logical(left: Expr<'a>, operator: Token<'a>, right: Expr<'a>) -> Self {
+        let span = left.span.start..right.span.end;
+        let node = ExprKind::Logical {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn set(object: Expr<'a>, name: Token<'a>, value: Expr<'a>) -> Self {
+        let span = object.span.start..value.span.end;
+        let node = ExprKind::Set {
+            object: Box::new(object),
+            name,
+            value: Box::new(value),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn ternary(condition: Expr<'a>, then_expr: Expr<'a>, else_expr: Expr<'a>) -> Self {
+        let span = condition.span.start..else_expr.span.end;
+        let node = ExprKind::Ternary {
+            condition: Box::new(condition),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn unary(operator: Token<'a>, right: Expr<'a>) -> Self {
+        let span = operator.span.start..right.span.end;
+        let node = ExprKind::Unary {
+            operator,
+            right: Box::new(right),
+        };
+        Spanned::new(node, span)
+    }
+
+    pub fn variable(name: Token<'a>) -> Self {
+        let span = name.span.clone();
+        Spanned::new(ExprKind::Variable(name), span)
+    }
+}
+
+// A pass over the tree, with one method per kind of expression; `accept`
+// does the dispatching, so a new pass only says what it does with each kind.
+pub trait ExprVisitor<'a, R> {
+    fn visit_array_literal(&mut self, elements: &[Expr<'a>]) -> R;
+    fn visit_assign(&mut self, name: &Token<'a>, value: &Expr<'a>) -> R;
+    fn visit_binary(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> R;
+    fn visit_call(&mut self, callee: &Expr<'a>, paren: &Token<'a>, arguments: &[Expr<'a>]) -> R;
+    fn visit_get(&mut self, object: &Expr<'a>, name: &Token<'a>) -> R;
+    fn visit_grouping(&mut self, expression: &Expr<'a>) -> R;
+    fn visit_index(&mut self, object: &Expr<'a>, bracket: &Token<'a>, index: &Expr<'a>) -> R;
+    fn visit_index_set(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        value: &Expr<'a>,
+    ) -> R;
+    fn visit_interpolation(&mut self, parts: &[Expr<'a>]) -> R;
+    fn visit_lambda(&mut self, keyword: &Token<'a>, params: &[Token<'a>], body: &[Stmt<'a>]) -> R;
+    fn visit_literal(&mut self, value: &LiteralValue<'a>) -> R;
+    fn visit_map_literal(&mut self, entries: &[(Expr<'a>, Expr<'a>)]) -> R;
+    fn visit_logical(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> R;
+    fn visit_ternary(
+        &mut self,
+        condition: &Expr<'a>,
+        then_expr: &Expr<'a>,
+        else_expr: &Expr<'a>,
+    ) -> R;
+    fn visit_set(&mut self, object: &Expr<'a>, name: &Token<'a>, value: &Expr<'a>) -> R;
+    fn visit_super(&mut self, keyword: &Token<'a>, method: &Token<'a>) -> R;
+    fn visit_this(&mut self, keyword: &Token<'a>) -> R;
+    fn visit_unary(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> R;
+    fn visit_variable(&mut self, name: &Token<'a>) -> R;
+}
+
+impl<'a> Expr<'a> {
+    pub fn accept<R>(&self, visitor: &mut impl ExprVisitor<'a, R>) -> R {
+        match &self.node {
+            ExprKind::ArrayLiteral(elements) => visitor.visit_array_literal(elements),
+            ExprKind::Assign { name, value } => visitor.visit_assign(name, value),
+            ExprKind::Binary {
+                left,
+                operator,
+                right,
+            } => visitor.visit_binary(left, operator, right),
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            } => visitor.visit_call(callee, paren, arguments),
+            ExprKind::Get { object, name } => visitor.visit_get(object, name),
+            ExprKind::Grouping(expression) => visitor.visit_grouping(expression),
+            ExprKind::Index {
+                object,
+                bracket,
+                index,
+            } => visitor.visit_index(object, bracket, index),
+            ExprKind::IndexSet {
+                object,
+                bracket,
+                index,
+                value,
+            } => visitor.visit_index_set(object, bracket, index, value),
+            ExprKind::Interpolation(parts) => visitor.visit_interpolation(parts),
+            ExprKind::Lambda {
+                keyword,
+                params,
+                body,
+            } => visitor.visit_lambda(keyword, params, body),
+            ExprKind::Literal(value) => visitor.visit_literal(value),
+            ExprKind::MapLiteral(entries) => visitor.visit_map_literal(entries),
+            ExprKind::Logical {
+                left,
+                operator,
+                right,
+            } => visitor.visit_logical(left, operator, right),
+            ExprKind::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => visitor.visit_ternary(condition, then_expr, else_expr),
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => visitor.visit_set(object, name, value),
+            ExprKind::Super { keyword, method } => visitor.visit_super(keyword, method),
+            ExprKind::This(keyword) => visitor.visit_this(keyword),
+            ExprKind::Unary { operator, right } => visitor.visit_unary(operator, right),
+            ExprKind::Variable(name) => visitor.visit_variable(name),
+        }
+    }
+}
+
+pub trait StmtVisitor<'a, R> {
+    fn visit_block(&mut self, statements: &[Stmt<'a>]) -> R;
+    fn visit_break(&mut self, keyword: &Token<'a>) -> R;
+    fn visit_class(
+        &mut self,
+        name: &Token<'a>,
+        superclass: Option<&Expr<'a>>,
+        methods: &[Function<'a>],
+    ) -> R;
+    fn visit_continue(&mut self, keyword: &Token<'a>) -> R;
+    fn visit_expression(&mut self, expression: &Expr<'a>) -> R;
+    fn visit_function(&mut self, function: &Function<'a>) -> R;
+    fn visit_if(
+        &mut self,
+        condition: &Expr<'a>,
+        then_branch: &Stmt<'a>,
+        else_branch: Option<&Stmt<'a>>,
+    ) -> R;
+    fn visit_print(&mut self, expression: &Expr<'a>) -> R;
+    fn visit_return(&mut self, keyword: &Token<'a>, value: Option<&Expr<'a>>) -> R;
+    fn visit_var(&mut self, name: &Token<'a>, initializer: Option<&Expr<'a>>) -> R;
+    fn visit_while(
+        &mut self,
+        condition: &Expr<'a>,
+        body: &Stmt<'a>,
+        increment: Option<&Expr<'a>>,
+        syntax: LoopSyntax,
+    ) -> R;
+}
+
+impl<'a> Stmt<'a> {
+    pub fn accept<R>(&self, visitor: &mut impl StmtVisitor<'a, R>) -> R {
+        match &self.node {
+            StmtKind::Block(statements) => visitor.visit_block(statements),
+            StmtKind::Break(keyword) => visitor.visit_break(keyword),
+            StmtKind::Class {
+                name,
+                superclass,
+                methods,
+            } => visitor.visit_class(name, superclass.as_ref(), methods),
+            StmtKind::Continue(keyword) => visitor.visit_continue(keyword),
+            StmtKind::Expression(expression) => visitor.visit_expression(expression),
+            StmtKind::Function(function) => visitor.visit_function(function),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+            StmtKind::Print(expression) => visitor.visit_print(expression),
+            StmtKind::Return { keyword, value } => visitor.visit_return(keyword, value.as_ref()),
+            StmtKind::Var { name, initializer } => visitor.visit_var(name, initializer.as_ref()),
+            StmtKind::While {
+                condition,
+                body,
+                increment,
+                syntax,
+            } => visitor.visit_while(condition, body, increment.as_ref(), *syntax),
+        }
+    }
+}
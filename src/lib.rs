@@ -1,17 +1,85 @@
-use std::{collections::VecDeque, mem, str::Chars};
+pub mod ast;
+pub mod parser;
+pub mod printer;
 
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    io::{self, BufRead},
+    iter::{self, FusedIterator},
+    ops::{ControlFlow, Range},
+    str,
+};
+
+#[derive(Debug)]
 pub enum LoxError {
-    ParseError { line: usize, message: String },
+    ParseError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // the tokens don't fit the grammar at `token`, which is EOF if they ran
+    // out; `expected` is the kind of token that would have fit, if only one
+    SyntaxError {
+        token: Token<'static>,
+        expected: Option<TokenType>,
+        message: String,
+    },
+    // reading the source failed part way through
+    IoError {
+        message: String,
+    },
+    // worth pointing out, but doesn't stop the program from running
+    Warning {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+// One line, as the CLI reports it: `[line 4:7] Error at ')': message`, after
+// jlox, which names the token a syntax error is at or says the tokens ran out.
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoxError::ParseError {
+                line,
+                column,
+                message,
+            } => write!(f, "[line {line}:{column}] Error: {message}"),
+            LoxError::SyntaxError { token, message, .. } => {
+                let (line, column) = (token.line, token.column);
+                match token.token_type {
+                    TokenType::EOF => write!(f, "[line {line}:{column}] Error at end: {message}"),
+                    _ => write!(
+                        f,
+                        "[line {line}:{column}] Error at '{}': {message}",
+                        token.lexeme
+                    ),
+                }
+            }
+            LoxError::IoError { message } => write!(f, "Error: {message}"),
+            LoxError::Warning {
+                line,
+                column,
+                message,
+            } => write!(f, "[line {line}:{column}] Warning: {message}"),
+        }
+    }
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenType {
     // Single-character tokens
     LEFT_PAREN,
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
     DOT,
     MINUS,
@@ -19,6 +87,13 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
+    QUESTION,
+    COLON,
+    AMP,
+    PIPE,
+    CARET,
+    TILDE,
 
     // One or two character tokens
     BANG,
@@ -27,22 +102,41 @@ pub enum TokenType {
     EQUAL_EQUAL,
     GREATER,
     GREATER_EQUAL,
+    GREATER_GREATER,
     LESS,
     LESS_EQUAL,
+    LESS_LESS,
+    MINUS_EQUAL,
+    MINUS_MINUS,
+    ARROW,
+    PLUS_EQUAL,
+    PLUS_PLUS,
+    SLASH_EQUAL,
+    STAR_EQUAL,
+    PERCENT_EQUAL,
 
     // Literals
     IDENTIFIER,
     STRING,
-    NUMBER(f64),
+    // the part of a string before an interpolated "${...}" expression, only
+    // with ScannerOptions::interpolation
+    INTERPOLATION,
+    NUMBER,
 
     // Keywords
     AND,
+    BREAK,
     CLASS,
+    // unless ScannerOptions::const_keyword is turned off
+    CONST,
+    CONTINUE,
     ELSE,
     FALSE,
     FUN,
     FOR,
     IF,
+    // only with ScannerOptions::in_keyword
+    IN,
     NIL,
     OR,
     PRINT,
@@ -53,130 +147,826 @@ pub enum TokenType {
     VAR,
     WHILE,
 
+    // only produced when ScannerOptions::comment_tokens is set
+    COMMENT,
+    // only produced when ScannerOptions::doc_comments is set
+    DOC_COMMENT,
+
     EOF,
 }
 
+const KEYWORDS: [(&str, TokenType); 20] = {
+    use TokenType::*;
+    [
+        ("and", AND),
+        ("break", BREAK),
+        ("class", CLASS),
+        ("const", CONST),
+        ("continue", CONTINUE),
+        ("else", ELSE),
+        ("false", FALSE),
+        ("fun", FUN),
+        ("for", FOR),
+        ("if", IF),
+        ("in", IN),
+        ("nil", NIL),
+        ("or", OR),
+        ("print", PRINT),
+        ("return", RETURN),
+        ("super", SUPER),
+        ("this", THIS),
+        ("true", TRUE),
+        ("var", VAR),
+        ("while", WHILE),
+    ]
+};
+
 impl TokenType {
-    fn to_keyword(s: &str) -> Option<TokenType> {
+    // the keyword spelled `s`, if it is one
+    //
+    // Matching on the length first rules out most identifiers with a single
+    // comparison and leaves at most seven same-length spellings to compare,
+    // which is quicker than hashing every identifier. The arms must agree
+    // with KEYWORDS.
+    pub fn to_keyword(s: &str) -> Option<TokenType> {
         use TokenType::*;
-        let t = match s {
-            "and" => AND,
-            "class" => CLASS,
-            "else" => ELSE,
-            "false" => FALSE,
-            "fun" => FUN,
-            "for" => FOR,
-            "if" => IF,
-            "nil" => NIL,
-            "or" => OR,
-            "print" => PRINT,
-            "return" => RETURN,
-            "super" => SUPER,
-            "this" => THIS,
-            "true" => TRUE,
-            "var" => VAR,
-            "while" => WHILE,
+        let keyword = match s.len() {
+            2 => match s {
+                "if" => IF,
+                "in" => IN,
+                "or" => OR,
+                _ => return None,
+            },
+            3 => match s {
+                "and" => AND,
+                "for" => FOR,
+                "fun" => FUN,
+                "nil" => NIL,
+                "var" => VAR,
+                _ => return None,
+            },
+            4 => match s {
+                "else" => ELSE,
+                "this" => THIS,
+                "true" => TRUE,
+                _ => return None,
+            },
+            5 => match s {
+                "break" => BREAK,
+                "class" => CLASS,
+                "const" => CONST,
+                "false" => FALSE,
+                "print" => PRINT,
+                "super" => SUPER,
+                "while" => WHILE,
+                _ => return None,
+            },
+            6 if s == "return" => RETURN,
+            8 if s == "continue" => CONTINUE,
             _ => return None,
         };
-        Some(t)
+        Some(keyword)
+    }
+
+    // the source spelling of a keyword
+    pub fn keyword_str(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, t)| t == self)
+            .map(|(keyword, _)| *keyword)
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        self.keyword_str().is_some()
+    }
+
+    // every keyword with its spelling, in declaration order
+    pub fn keywords() -> &'static [(&'static str, TokenType)] {
+        &KEYWORDS
     }
 }
 
-#[derive(Debug)]
-pub struct Token {
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal<'a> {
+    Number(f64),
+    // borrowed from the source unless escapes had to be processed
+    Str(Cow<'a, str>),
+}
+
+impl Literal<'_> {
+    pub fn into_owned(self) -> Literal<'static> {
+        match self {
+            Literal::Number(n) => Literal::Number(n),
+            Literal::Str(s) => Literal::Str(Cow::Owned(s.into_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // like Java's Double.toString, always with a fractional part
+            Literal::Number(n) => write!(f, "{n:?}"),
+            Literal::Str(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub lexeme: String,
+    // borrowed from the source, unless it was read from a stream
+    pub lexeme: Cow<'a, str>,
+    // the value of NUMBER, STRING and INTERPOLATION tokens
+    pub literal: Option<Literal<'a>>,
     pub line: usize,
+    // 1-based, counted in characters
+    pub column: usize,
+    // byte offsets into the source
+    pub span: Range<usize>,
+}
+
+impl Token<'_> {
+    // Detaches the token from the source it was scanned from.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            token_type: self.token_type,
+            lexeme: Cow::Owned(self.lexeme.into_owned()),
+            literal: self.literal.map(Literal::into_owned),
+            line: self.line,
+            column: self.column,
+            span: self.span,
+        }
+    }
+}
+
+// Formats as jlox does: `TYPE lexeme literal`, with `null` for no literal.
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", self.token_type, self.lexeme)?;
+        match &self.literal {
+            Some(literal) => write!(f, "{literal}"),
+            None => write!(f, "null"),
+        }
+    }
+}
+
+// Extensions to the book's Lox are off by default, so that a scanner accepts
+// what jlox does, save for the `const` keyword, and can be switched on one by
+// one, either directly or in a chain of builder calls:
+// `ScannerOptions::default().hex_literals(true).raw_strings(true)`.
+#[derive(Debug, Clone)]
+pub struct ScannerOptions {
+    // emit COMMENT tokens instead of skipping comments
+    pub comment_tokens: bool,
+    // emit DOC_COMMENT tokens for `///` comments, whatever comment_tokens is
+    pub doc_comments: bool,
+    // a raw newline inside a string literal leaves it unterminated
+    pub strict_newlines: bool,
+    // 0x, 0b and 0o integer literals
+    pub hex_literals: bool,
+    // strings may be quoted with ' as well as "
+    pub single_quotes: bool,
+    // r"..." strings, where backslashes are literal
+    pub raw_strings: bool,
+    // "${expr}" in a string interpolates the expression; otherwise "${" is
+    // just two characters of the string, as in the book
+    pub interpolation: bool,
+    // identifiers may use any Unicode letter, not just ASCII ones
+    pub unicode_identifiers: bool,
+    // `in` is a keyword, for `for (x in xs)`, taking a name that programs
+    // may already use
+    pub in_keyword: bool,
+    // `const` is a keyword, for single-assignment variables. On by default,
+    // as it is rarely wanted for a name; off, it is an identifier as in the
+    // book.
+    pub const_keyword: bool,
+}
+
+impl Default for ScannerOptions {
+    fn default() -> Self {
+        ScannerOptions {
+            comment_tokens: false,
+            doc_comments: false,
+            strict_newlines: false,
+            hex_literals: false,
+            single_quotes: false,
+            raw_strings: false,
+            interpolation: false,
+            unicode_identifiers: false,
+            in_keyword: false,
+            const_keyword: true,
+        }
+    }
+}
+
+impl ScannerOptions {
+    pub fn comment_tokens(mut self, enabled: bool) -> Self {
+        self.comment_tokens = enabled;
+        self
+    }
+
+    pub fn doc_comments(mut self, enabled: bool) -> Self {
+        self.doc_comments = enabled;
+        self
+    }
+
+    pub fn strict_newlines(mut self, enabled: bool) -> Self {
+        self.strict_newlines = enabled;
+        self
+    }
+
+    pub fn hex_literals(mut self, enabled: bool) -> Self {
+        self.hex_literals = enabled;
+        self
+    }
+
+    pub fn single_quotes(mut self, enabled: bool) -> Self {
+        self.single_quotes = enabled;
+        self
+    }
+
+    pub fn raw_strings(mut self, enabled: bool) -> Self {
+        self.raw_strings = enabled;
+        self
+    }
+
+    pub fn interpolation(mut self, enabled: bool) -> Self {
+        self.interpolation = enabled;
+        self
+    }
+
+    pub fn unicode_identifiers(mut self, enabled: bool) -> Self {
+        self.unicode_identifiers = enabled;
+        self
+    }
+
+    pub fn in_keyword(mut self, enabled: bool) -> Self {
+        self.in_keyword = enabled;
+        self
+    }
+
+    pub fn const_keyword(mut self, enabled: bool) -> Self {
+        self.const_keyword = enabled;
+        self
+    }
+
+    // the keyword spelled `s` in this dialect, if it is one
+    fn keyword(&self, s: &str) -> Option<TokenType> {
+        match TokenType::to_keyword(s)? {
+            TokenType::IN if !self.in_keyword => None,
+            TokenType::CONST if !self.const_keyword => None,
+            keyword => Some(keyword),
+        }
+    }
+}
+
+// Bounds on what a scanner will accept from untrusted input; None is
+// unlimited. Exceeding any of them is reported once and ends scanning.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScannerLimits {
+    pub max_source_bytes: Option<usize>,
+    // COMMENT and DOC_COMMENT tokens count, EOF does not
+    pub max_tokens: Option<usize>,
+    // in bytes of source text, quotes included
+    pub max_string_length: Option<usize>,
+    pub max_number_length: Option<usize>,
+}
+
+// Counts gathered by scan_tokens_with_stats.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanStats {
+    // every token, EOF included
+    pub tokens: usize,
+    pub tokens_by_type: HashMap<TokenType, usize>,
+    // a final line without a newline counts; an empty source has none
+    pub lines: usize,
+    // including the "//", "/*" and "*/"
+    pub comment_chars: usize,
+    // in characters
+    pub longest_token: usize,
+}
+
+impl ScanStats {
+    fn record(&mut self, token: &Token) {
+        self.tokens += 1;
+        *self.tokens_by_type.entry(token.token_type).or_default() += 1;
+        self.longest_token = self.longest_token.max(token.lexeme.chars().count());
+    }
+
+    fn finish(&mut self, line: usize, column: usize, comment_chars: usize) {
+        // column 1 means the source is empty or ended with a newline
+        self.lines = if column == 1 { line - 1 } else { line };
+        self.comment_chars = comment_chars;
+    }
+}
+
+// An open "${" in a string, which ends at the "}" at depth 0.
+#[derive(Clone)]
+struct Interpolation {
+    // the quote of the enclosing string
+    quote: char,
+    depth: usize,
+    line: usize,
+    column: usize,
+}
+
+// Unexpected characters, up to where scanning resynchronized, reported as a
+// single error.
+#[derive(Clone)]
+struct UnexpectedRun {
+    line: usize,
+    column: usize,
+    text: String,
+    // byte offset just past the last character of the run
+    end: usize,
+}
+
+impl UnexpectedRun {
+    fn into_error(self) -> LoxError {
+        let mut chars = self.text.chars();
+        let message = match (chars.next(), chars.next()) {
+            (Some(c), None) => format!(
+                "Unexpected character '{}' (U+{:04X}).",
+                c.escape_debug(),
+                c as u32
+            ),
+            _ => format!("Unexpected characters \"{}\".", self.text.escape_debug()),
+        };
+        LoxError::ParseError {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+}
+
+// What a scanner carries from one token to the next, so that a stream can
+// be scanned one buffer at a time.
+#[derive(Clone)]
+struct ScanState {
+    // byte offset of the buffer in the whole input
+    offset: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    interpolations: Vec<Interpolation>,
+    previous: Option<TokenType>,
+    unexpected: Option<UnexpectedRun>,
+    tokens: usize,
+    comment_chars: usize,
+    halted: bool,
+    // the input went on past ScannerLimits::max_source_bytes
+    truncated: bool,
+    eof_emitted: bool,
+}
+
+impl Default for ScanState {
+    fn default() -> Self {
+        ScanState {
+            offset: 0,
+            line: 1,
+            column: 1,
+            end_line: 1,
+            end_column: 1,
+            interpolations: Vec::new(),
+            previous: None,
+            unexpected: None,
+            tokens: 0,
+            comment_chars: 0,
+            halted: false,
+            truncated: false,
+            eof_emitted: false,
+        }
+    }
 }
 
 pub struct Scanner<'a> {
-    // iterator
-    chars: Chars<'a>,
-    buf: VecDeque<char>,
-    // scanner
-    tokens: Vec<Token>,
-    current_string: String,
+    source: &'a str,
+    // byte offset of the source in the whole input; spans include it
+    offset: usize,
+    options: ScannerOptions,
+    limits: ScannerLimits,
+    // scanned tokens and errors not yet handed out by next_token
+    pending: VecDeque<Result<Token<'a>, LoxError>>,
+    unexpected: Option<UnexpectedRun>,
     line: usize,
+    // column of the next character to be read
+    column: usize,
+    // just past the last token, comment or error, ignoring whitespace; EOF is
+    // reported here so that a trailing newline doesn't put it on a line of
+    // its own
+    end_line: usize,
+    end_column: usize,
+    // line and column of the first character of the current token
+    start_line: usize,
+    start_column: usize,
+    // byte offset of the first character of the current token
+    start: usize,
+    // byte offset of the next character to be read
+    current: usize,
+    interpolations: Vec<Interpolation>,
+    // type of the last token, ignoring comments
+    previous: Option<TokenType>,
+    // tokens pushed so far, for ScannerLimits::max_tokens
+    tokens: usize,
+    // characters in comments, for ScanStats
+    comment_chars: usize,
+    // a limit was exceeded, so nothing more will be scanned
+    halted: bool,
+    // the source was cut short at ScannerLimits::max_source_bytes
+    truncated: bool,
+    eof_emitted: bool,
+    // set when lookahead ran off the end of the source, which for a stream
+    // means the last result may change once more input has been read
+    starved: Cell<bool>,
 }
 
-impl Iterator for Scanner<'_> {
-    type Item = char;
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token<'a>, LoxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.buf.is_empty() {
-            self.chars.next()
-        } else {
-            self.buf.pop_front()
-        }
+        self.next_token()
     }
 }
 
+impl FusedIterator for Scanner<'_> {}
+
+// The source is read byte by byte; only non-ASCII text pays for decoding.
 impl Scanner<'_> {
-    fn peek(&mut self) -> Option<char> {
-        if self.buf.is_empty() {
-            let c = self.chars.next()?;
-            self.buf.push_back(c);
+    fn peek(&self) -> Option<char> {
+        let Some(&b) = self.source.as_bytes().get(self.current) else {
+            self.starved.set(true);
+            return None;
+        };
+        match b {
+            b if b.is_ascii() => Some(b as char),
+            _ => self.source[self.current..].chars().next(),
         }
-        self.buf.front().cloned()
     }
 
-    fn peek_next(&mut self) -> Option<char> {
-        while self.buf.len() < 2 {
-            let c = self.chars.next()?;
-            self.buf.push_back(c);
+    fn peek_next(&self) -> Option<char> {
+        self.peek_nth(1)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        let bytes = &self.source.as_bytes()[self.current..];
+        if bytes.len() > n && bytes[..=n].is_ascii() {
+            Some(bytes[n] as char)
+        } else {
+            let c = self.source[self.current..].chars().nth(n);
+            self.starved.set(self.starved.get() || c.is_none());
+            c
         }
-        self.buf.get(1).cloned()
     }
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
+        Scanner::with_options(source, ScannerOptions::default())
+    }
+
+    // A leading byte order mark is skipped. Spans stay relative to the
+    // original source, so the first token starts at byte 3.
+    pub fn with_options(source: &'a str, options: ScannerOptions) -> Self {
+        Scanner::with_limits(source, options, ScannerLimits::default())
+    }
+
+    // Only the first max_source_bytes of a longer source are scanned; the
+    // limit error then takes the place of EOF.
+    pub fn with_limits(source: &'a str, options: ScannerOptions, limits: ScannerLimits) -> Self {
+        let mut state = ScanState::default();
+        let source = match limits.max_source_bytes {
+            Some(max) if source.len() > max => {
+                state.truncated = true;
+                &source[..source.floor_char_boundary(max)]
+            }
+            _ => source,
+        };
+        Scanner::resume(source, options, limits, state)
+    }
+
+    // Scans `reader` incrementally, keeping only the unscanned part of the
+    // last chunk read in memory. Tokens are owned rather than borrowed.
+    pub fn from_reader<R: BufRead>(reader: R, options: ScannerOptions) -> StreamScanner<R> {
+        Scanner::from_reader_with_limits(reader, options, ScannerLimits::default())
+    }
+
+    pub fn from_reader_with_limits<R: BufRead>(
+        reader: R,
+        options: ScannerOptions,
+        limits: ScannerLimits,
+    ) -> StreamScanner<R> {
+        StreamScanner {
+            reader,
+            options,
+            limits,
+            buffer: String::new(),
+            position: 0,
+            undecoded: Vec::new(),
+            state: ScanState::default(),
+            pending: VecDeque::new(),
+            eof: false,
+            read_error: None,
+        }
+    }
+
+    fn resume(
+        source: &'a str,
+        options: ScannerOptions,
+        limits: ScannerLimits,
+        state: ScanState,
+    ) -> Self {
+        let bom_len = if state.offset == 0 && source.starts_with('\u{feff}') {
+            '\u{feff}'.len_utf8()
+        } else {
+            0
+        };
         Scanner {
-            chars: source.chars(),
-            tokens: Default::default(),
-            current_string: Default::default(),
-            buf: Default::default(),
-            line: 1,
+            source,
+            offset: state.offset,
+            options,
+            limits,
+            pending: Default::default(),
+            unexpected: state.unexpected,
+            line: state.line,
+            column: state.column,
+            end_line: state.end_line,
+            end_column: state.end_column,
+            start_line: state.line,
+            start_column: state.column,
+            start: bom_len,
+            current: bom_len,
+            interpolations: state.interpolations,
+            previous: state.previous,
+            tokens: state.tokens,
+            comment_chars: state.comment_chars,
+            halted: state.halted,
+            truncated: state.truncated,
+            eof_emitted: state.eof_emitted,
+            starved: Cell::new(false),
+        }
+    }
+
+    // The state for scanning on from `current` in a buffer that starts there.
+    fn suspend(self) -> ScanState {
+        let current = self.current;
+        ScanState {
+            offset: self.offset + current,
+            line: self.line,
+            column: self.column,
+            end_line: self.end_line,
+            end_column: self.end_column,
+            interpolations: self.interpolations,
+            previous: self.previous,
+            unexpected: self.unexpected.map(|run| UnexpectedRun {
+                end: run.end - current,
+                ..run
+            }),
+            tokens: self.tokens,
+            comment_chars: self.comment_chars,
+            halted: self.halted,
+            truncated: self.truncated,
+            eof_emitted: self.eof_emitted,
         }
     }
 }
 
-impl Scanner<'_> {
-    pub fn scan_tokens(mut self) -> Result<Vec<Token>, LoxError> {
-        while !self.is_at_end() {
-            self.scan_token()?;
+impl<'a> Scanner<'a> {
+    pub fn scan_tokens(mut self) -> Result<Vec<Token<'a>>, Vec<LoxError>> {
+        self.scan_all()
+    }
+
+    pub fn scan_tokens_with_stats(mut self) -> (Result<Vec<Token<'a>>, Vec<LoxError>>, ScanStats) {
+        let mut stats = ScanStats::default();
+        let result = collect_tokens(self.by_ref().inspect(|result| {
+            if let Ok(token) = result {
+                stats.record(token);
+            }
+        }));
+        stats.finish(self.line, self.column, self.comment_chars);
+        (result, stats)
+    }
+
+    // Scans the rest of the source, leaving the scanner usable. Once EOF has
+    // been returned there is nothing left, so further calls return no tokens.
+    pub fn scan_all(&mut self) -> Result<Vec<Token<'a>>, Vec<LoxError>> {
+        collect_tokens(self.by_ref())
+    }
+
+    // Hands each token to `f` as it is scanned rather than collecting them,
+    // EOF included. Errors are gathered and returned once `f` breaks or the
+    // source runs out; a break leaves the rest of the source to scan later.
+    pub fn scan_with(
+        &mut self,
+        f: impl FnMut(Token<'a>) -> ControlFlow<()>,
+    ) -> Result<(), Vec<LoxError>> {
+        sink_tokens(self.by_ref(), f)
+    }
+
+    // Produces tokens on demand, ending with a single EOF token and then None
+    // for good. An error only affects the token being scanned; scanning
+    // resumes after it.
+    pub fn next_token(&mut self) -> Option<Result<Token<'a>, LoxError>> {
+        loop {
+            self.skip_whitespace();
+            // a run of unexpected characters ends at the first character that isn't one
+            let at_end = self.is_at_end();
+            let current = self.current;
+            if let Some(run) = self.unexpected.take_if(|run| at_end || run.end < current) {
+                return Some(Err(run.into_error()));
+            }
+            if let Some(result) = self.pending.pop_front() {
+                return Some(result);
+            }
+            if self.halted {
+                return None;
+            }
+            if self.is_at_end() {
+                if self.truncated {
+                    let max = self.limits.max_source_bytes.unwrap_or_default();
+                    return Some(Err(self.limit_exceeded(format!(
+                        "Source is longer than the limit of {max} bytes."
+                    ))));
+                }
+                if let Some(interpolation) = self.interpolations.pop() {
+                    return Some(Err(LoxError::ParseError {
+                        line: interpolation.line,
+                        column: interpolation.column,
+                        message: String::from("Unterminated string interpolation."),
+                    }));
+                }
+                if self.eof_emitted {
+                    return None;
+                }
+                self.eof_emitted = true;
+                let end = self.offset + self.current;
+                return Some(Ok(Token {
+                    token_type: TokenType::EOF,
+                    lexeme: Cow::Borrowed(""),
+                    literal: None,
+                    line: self.end_line,
+                    column: self.end_column,
+                    span: end..end,
+                }));
+            }
+            let result = self.scan_token();
+            self.end_line = self.line;
+            self.end_column = self.column;
+            if let Err(error) = result {
+                self.pending.push_back(Err(error));
+            }
         }
+    }
 
-        self.tokens.push(Token {
-            token_type: TokenType::EOF,
-            lexeme: String::from(""),
+    // Any limit error ends scanning; more input could only make it worse.
+    fn limit_exceeded(&mut self, message: String) -> LoxError {
+        self.halted = true;
+        LoxError::ParseError {
             line: self.line,
-        });
+            column: self.column,
+            message,
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        let at_end = self.current >= self.source.len();
+        self.starved.set(self.starved.get() || at_end);
+        at_end
+    }
 
-        Ok(self.tokens)
+    // Skips ASCII bytes accepted by the predicate, which must reject line
+    // endings, '\n' and '\r'.
+    fn skip_ascii(&mut self, accept: impl Fn(u8) -> bool) {
+        let rest = &self.source.as_bytes()[self.current..];
+        let n = rest
+            .iter()
+            .take_while(|b| b.is_ascii() && accept(**b))
+            .count();
+        self.starved.set(self.starved.get() || n == rest.len());
+        self.current += n;
+        self.column += n;
     }
 
-    fn is_at_end(&mut self) -> bool {
-        self.peek().is_none() && self.buf.is_empty()
+    fn skip_whitespace(&mut self) {
+        while let Some(&b) = self.source.as_bytes().get(self.current) {
+            match b {
+                // vertical tab and form feed too, as in C
+                b' ' | b'\t' | b'\x0B' | b'\x0C' => {
+                    self.column += 1;
+                    self.current += 1;
+                }
+                b'\n' | b'\r' => {
+                    self.advance();
+                }
+                _ => return,
+            }
+        }
+        self.starved.set(true);
     }
 
     fn scan_token(&mut self) -> Result<(), LoxError> {
-        use LoxError::*;
         use TokenType::*;
 
-        let c = self.advance().expect("Reading past end");
+        self.start_line = self.line;
+        self.start_column = self.column;
+        self.start = self.current;
+        let Some(c) = self.advance() else {
+            return Err(LoxError::ParseError {
+                line: self.line,
+                column: self.column,
+                message: String::from("Unexpected end of input."),
+            });
+        };
 
         match c {
             '(' => self.add_token(LEFT_PAREN),
             ')' => self.add_token(RIGHT_PAREN),
-            '{' => self.add_token(LEFT_BRACE),
-            '}' => self.add_token(RIGHT_BRACE),
+            '{' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth += 1;
+                }
+                self.add_token(LEFT_BRACE);
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(interpolation) if interpolation.depth == 0 => {
+                    // the rest of the string continues after the interpolation
+                    let quote = interpolation.quote;
+                    self.interpolations.pop();
+                    self.string(quote)?;
+                }
+                Some(interpolation) => {
+                    interpolation.depth -= 1;
+                    self.add_token(RIGHT_BRACE);
+                }
+                None => self.add_token(RIGHT_BRACE),
+            },
+            '[' => self.add_token(LEFT_BRACKET),
+            ']' => self.add_token(RIGHT_BRACKET),
             ',' => self.add_token(COMMA),
-            '.' => self.add_token(DOT),
-            '-' => self.add_token(MINUS),
-            '+' => self.add_token(PLUS),
+            '.' => {
+                // ".5" is a number, but "x.5" stays a DOT after an operand
+                if self.peek().filter(|c| c.is_ascii_digit()).is_some() && !self.follows_operand() {
+                    self.number()?;
+                } else {
+                    self.add_token(DOT);
+                }
+            }
             ';' => self.add_token(SEMICOLON),
-            '*' => self.add_token(STAR),
+            '?' => self.add_token(QUESTION),
+            ':' => self.add_token(COLON),
+            '&' => self.add_token(AMP),
+            '|' => self.add_token(PIPE),
+            '^' => self.add_token(CARET),
+            '~' => self.add_token(TILDE),
+            '-' => {
+                // "--" is always one token, even in "--x" or "a--b"; telling
+                // decrement from double negation is left to the parser. Being
+                // greedy, "-->" is MINUS_MINUS then GREATER.
+                let token = if self.match_char('-') {
+                    MINUS_MINUS
+                } else if self.match_char('>') {
+                    ARROW
+                } else if self.match_char('=') {
+                    MINUS_EQUAL
+                } else {
+                    MINUS
+                };
+                self.add_token(token);
+            }
+            '+' => {
+                let token = if self.match_char('+') {
+                    PLUS_PLUS
+                } else if self.match_char('=') {
+                    PLUS_EQUAL
+                } else {
+                    PLUS
+                };
+                self.add_token(token);
+            }
+            '*' => {
+                let token = if self.match_char('=') {
+                    STAR_EQUAL
+                } else {
+                    STAR
+                };
+                self.add_token(token);
+            }
+            '%' => {
+                let token = if self.match_char('=') {
+                    PERCENT_EQUAL
+                } else {
+                    PERCENT
+                };
+                self.add_token(token);
+            }
             '!' => {
                 let token = if self.match_char('=') {
                     BANG_EQUAL
@@ -194,7 +984,10 @@ impl Scanner<'_> {
                 self.add_token(token);
             }
             '<' => {
-                let token = if self.match_char('=') {
+                // "<<=" is LESS_LESS then EQUAL
+                let token = if self.match_char('<') {
+                    LESS_LESS
+                } else if self.match_char('=') {
                     LESS_EQUAL
                 } else {
                     LESS
@@ -202,7 +995,9 @@ impl Scanner<'_> {
                 self.add_token(token);
             }
             '>' => {
-                let token = if self.match_char('=') {
+                let token = if self.match_char('>') {
+                    GREATER_GREATER
+                } else if self.match_char('=') {
                     GREATER_EQUAL
                 } else {
                     GREATER
@@ -212,122 +1007,565 @@ impl Scanner<'_> {
             '/' => {
                 if self.match_char('/') {
                     // A comment goes until the end of the line.
-                    while self.peek().filter(|c| *c != '\n').is_some() {
+                    self.skip_ascii(|b| !matches!(b, b'\n' | b'\r'));
+                    while self.peek().filter(|c| !is_line_ending(*c)).is_some() {
                         self.advance();
                     }
-                    // ignore parsing comment
-                    self.current_string.clear();
+                    self.add_comment_token();
+                } else if self.match_char('*') {
+                    self.block_comment()?;
+                    self.add_comment_token();
+                } else if self.match_char('=') {
+                    self.add_token(SLASH_EQUAL);
                 } else {
                     self.add_token(SLASH);
                 }
             }
-            ' ' | '\r' | '\t' => {
-                self.current_string.clear();
-                return Ok(());
+            // a "#!" line at the very start makes scripts executable
+            '#' if self.offset == 0
+                && matches!(&self.source[..self.start], "" | "\u{feff}")
+                && self.match_char('!') =>
+            {
+                while self.peek().filter(|c| !is_line_ending(*c)).is_some() {
+                    self.advance();
+                }
             }
-            '\n' => {
-                self.line += 1;
-                return Ok(());
+            '"' => self.string(c)?,
+            '\'' if self.options.single_quotes => self.string(c)?,
+            'r' if self.options.raw_strings && self.peek().is_some_and(|c| self.is_quote(c)) => {
+                self.raw_string()?
             }
-            '"' => self.string()?,
             '0'..='9' => self.number()?,
-            'a'..='z' | 'A'..='Z' | '_' => self.identifier()?,
+            // keywords are ASCII, but identifiers may use any Unicode letter
+            c if self.is_identifier_start(c) => self.identifier()?,
+            // usually pasted in from a chat app or a PDF, and invisible
+            '\u{a0}' => {
+                return Err(LoxError::ParseError {
+                    line: self.line,
+                    column: self.start_column,
+                    message: String::from(
+                        "Non-breaking space found; replace with a regular space.",
+                    ),
+                });
+            }
             _ => {
-                return Err(ParseError {
+                // Resynchronize at whitespace or punctuation, so that the rest
+                // of a corrupted word like "le\u{a9}t" isn't scanned as tokens
+                // of its own. Unexpected characters up to there join the run.
+                let mut text = String::from(c);
+                while let Some(c) = self
+                    .peek()
+                    .filter(|c| !c.is_whitespace() && !c.is_ascii_punctuation())
+                {
+                    self.advance();
+                    if !c.is_alphanumeric() && c != '_' {
+                        text.push(c);
+                    }
+                }
+                let run = self.unexpected.get_or_insert_with(|| UnexpectedRun {
                     line: self.line,
-                    message: String::from("Unexpected character."),
-                })
+                    column: self.start_column,
+                    text: String::new(),
+                    end: 0,
+                });
+                run.text.push_str(&text);
+                run.end = self.current;
             }
         };
 
         Ok(())
     }
 
+    fn is_quote(&self, c: char) -> bool {
+        c == '"' || (c == '\'' && self.options.single_quotes)
+    }
+
+    fn is_identifier_start(&self, c: char) -> bool {
+        match c {
+            'a'..='z' | 'A'..='Z' | '_' => true,
+            _ => self.options.unicode_identifiers && c.is_alphabetic(),
+        }
+    }
+
+    fn block_comment(&mut self) -> Result<(), LoxError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                Some('/') if self.match_char('*') => depth += 1,
+                Some('*') if self.match_char('/') => depth -= 1,
+                Some(_) => {}
+                None => {
+                    return Err(LoxError::ParseError {
+                        // where the opening "/*" was, not where scanning gave up
+                        line: self.start_line,
+                        column: self.start_column,
+                        message: String::from("Unterminated block comment."),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn identifier(&mut self) -> Result<(), LoxError> {
-        while self
-            .peek()
-            .filter(|c| matches!(*c, 'a'..='z' | 'A'..='Z' | '_'| '0'..='9'))
-            .is_some()
+        self.skip_ascii(|b| b.is_ascii_alphanumeric() || b == b'_');
+        while self.options.unicode_identifiers
+            && self
+                .peek()
+                .filter(|c| c.is_alphanumeric() || *c == '_')
+                .is_some()
         {
             self.advance();
         }
-        if let Some(t) = TokenType::to_keyword(&self.current_string) {
-            self.add_token(t);
-        } else {
-            self.add_token(TokenType::IDENTIFIER);
-        }
+        let keyword = self.options.keyword(&self.source[self.start..self.current]);
+        self.add_token(keyword.unwrap_or(TokenType::IDENTIFIER));
         Ok(())
     }
 
     fn number(&mut self) -> Result<(), LoxError> {
-        while self.peek().filter(|c| matches!(*c, '0'..='9')).is_some() {
-            self.advance();
+        if self.options.hex_literals && self.source[self.start..].starts_with('0') {
+            if self.match_char('x') || self.match_char('X') {
+                return self.radix_number(16, "hexadecimal");
+            }
+            if self.match_char('b') || self.match_char('B') {
+                return self.radix_number(2, "binary");
+            }
+            if self.match_char('o') || self.match_char('O') {
+                return self.radix_number(8, "octal");
+            }
         }
 
-        // Look for a fractional part
-        if self.peek().filter(|c| *c == '.').is_some()
-            && self
-                .peek_next()
-                .filter(|c| matches!(*c, '0'..='9'))
-                .is_some()
+        let mut valid = self.decimal_digits();
+
+        // Look for a fractional part, unless the number started with one
+        if !self.source[self.start..].starts_with('.')
+            && self.peek().filter(|c| *c == '.').is_some()
+            && self.peek_next().filter(|c| c.is_ascii_digit()).is_some()
         {
             // Consume the "."
             self.advance();
 
-            while self.peek().filter(|c| matches!(*c, '0'..='9')).is_some() {
-                self.advance();
-            }
+            valid &= self.decimal_digits();
+        } else if !self.source[self.start..].starts_with('.')
+            && self.peek() == Some('.')
+            && self
+                .peek_next()
+                .filter(|c| c.is_alphabetic() || *c == '_')
+                .is_none()
+        {
+            // "123." is a mistake, while "123.sqrt()" is a method call
+            self.advance();
+            return Err(LoxError::ParseError {
+                line: self.line,
+                column: self.start_column,
+                message: String::from("Expected digits after decimal point."),
+            });
         }
-        let num: f64 = self.current_string.parse().expect("current_string not f64");
-        self.add_token(TokenType::NUMBER(num));
-        Ok(())
-    }
 
-    fn string(&mut self) -> Result<(), LoxError> {
-        while self.peek().filter(|c| *c != '"').is_some() {
-            if self.peek().filter(|c| *c == '\n').is_some() {
-                self.line += 1;
+        // Look for an exponent, leaving a dangling "e" to scan as an identifier
+        if self.peek().filter(|c| matches!(c, 'e' | 'E')).is_some() {
+            let digit_at = match self.peek_next() {
+                Some('+' | '-') => 2,
+                _ => 1,
+            };
+            if self
+                .peek_nth(digit_at)
+                .filter(|c| c.is_ascii_digit())
+                .is_some()
+            {
+                // Consume the "e" and the sign
+                for _ in 0..digit_at {
+                    self.advance();
+                }
+                valid &= self.decimal_digits();
             }
-            self.advance();
         }
-        if self.is_at_end() {
+
+        self.check_number_length()?;
+        let lexeme = &self.source[self.start..self.current];
+        if !valid {
             return Err(LoxError::ParseError {
                 line: self.line,
-                message: String::from("Unterminated string."),
+                column: self.start_column,
+                message: format!("Invalid number literal '{lexeme}'."),
             });
         }
-        // The closing ".
-        self.advance();
-        // remove the surrounding "
-        self.current_string = self.current_string[1..self.current_string.len() - 1].to_string();
-        self.add_token(TokenType::STRING);
+        let digits = if lexeme.contains('_') {
+            Cow::Owned(lexeme.replace('_', ""))
+        } else {
+            Cow::Borrowed(lexeme)
+        };
+        // one too large for an f64 parses as infinity, which isn't a literal
+        let num = digits
+            .parse::<f64>()
+            .ok()
+            .filter(|num| num.is_finite())
+            .ok_or_else(|| LoxError::ParseError {
+                line: self.line,
+                column: self.start_column,
+                message: format!("Invalid number literal '{lexeme}'."),
+            })?;
+        self.add_token_with_literal(TokenType::NUMBER, Literal::Number(num));
         Ok(())
     }
 
-    fn advance(&mut self) -> Option<char> {
-        let c = self.next()?;
-        self.current_string.push(c);
-        Some(c)
+    fn follows_operand(&self) -> bool {
+        use TokenType::*;
+        matches!(
+            self.previous,
+            Some(IDENTIFIER | NUMBER | STRING | RIGHT_PAREN | THIS | SUPER)
+        )
     }
 
-    fn add_token(&mut self, token_type: TokenType) {
-        let text = mem::take(&mut self.current_string);
-        self.tokens.push(Token {
-            token_type,
-            lexeme: text,
-            line: self.line,
-        });
+    // Consumes digits separated by single underscores, returning false if an
+    // underscore is doubled or trailing.
+    fn decimal_digits(&mut self) -> bool {
+        let mut valid = true;
+        let mut underscore = false;
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit() || *c == '_') {
+            self.advance();
+            if c == '_' {
+                valid &= !underscore;
+                underscore = true;
+            } else {
+                underscore = false;
+            }
+        }
+        valid && !underscore
     }
 
-    fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            return false;
-        }
-        let c = match self.peek() {
-            Some(c) => c,
-            None => return false,
-        };
-        if c == expected {
+    // Scans the digits after a 0x, 0b or 0o prefix. Single underscores may
+    // separate digits. Values above 2^53 are rounded to the nearest f64, once,
+    // as decimal literals are.
+    fn radix_number(&mut self, radix: u32, name: &str) -> Result<(), LoxError> {
+        // The value is `value * 2^shift`, plus something less than 2^shift
+        // that isn't zero if `inexact`. Once the digits outgrow a u128 the
+        // ones after that can only decide which way the value rounds, and
+        // that only as far as whether they are all zero.
+        let bits = radix.trailing_zeros();
+        let mut value: u128 = 0;
+        let mut shift = 0;
+        let mut inexact = false;
+        let mut digits = 0;
+        let mut underscore = false;
+        // a letter or digit running into the literal makes it malformed
+        let mut malformed = false;
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            self.advance();
+            match c.to_digit(radix) {
+                Some(d) => {
+                    if shift == 0 && value.leading_zeros() >= bits {
+                        value = value << bits | d as u128;
+                    } else {
+                        shift += bits;
+                        inexact |= d != 0;
+                    }
+                    digits += 1;
+                    underscore = false;
+                }
+                None if c == '_' && digits > 0 && !underscore => underscore = true,
+                None => malformed = true,
+            }
+        }
+        self.check_number_length()?;
+        // a u128 this long has its low bit far below an f64's precision, so
+        // setting it for the digits dropped rounds the same as they would
+        let value = if inexact { value | 1 } else { value };
+        let num = value as f64 * 2f64.powi(shift as i32);
+        // past 2^1024 the value rounds to infinity, which isn't a literal
+        if digits == 0 || underscore || malformed || !num.is_finite() {
+            return Err(LoxError::ParseError {
+                line: self.line,
+                column: self.start_column,
+                message: format!(
+                    "Invalid {name} literal '{}'.",
+                    &self.source[self.start..self.current]
+                ),
+            });
+        }
+        self.add_token_with_literal(TokenType::NUMBER, Literal::Number(num));
+        Ok(())
+    }
+
+    // Scans from an opening quote, or from the "}" ending an interpolation, up
+    // to the closing quote or, with interpolation, the next "${". Either quote
+    // may delimit strings.
+    fn string(&mut self, quote: char) -> Result<(), LoxError> {
+        // only allocated once an escape sequence is found
+        let mut value: Option<String> = None;
+        // a bad escape is reported once the whole string has been consumed
+        let mut error = None;
+        let mut token_type = TokenType::STRING;
+        let strict = self.options.strict_newlines;
+        loop {
+            let skipped = self.current;
+            self.skip_ascii(|b| !matches!(b, b'\\' | b'\n' | b'\r' | b'$' | b'"' | b'\''));
+            self.check_string_length()?;
+            if let Some(value) = value.as_mut() {
+                value.push_str(&self.source[skipped..self.current]);
+            }
+            let Some(c) = self
+                .peek()
+                .filter(|c| *c != quote && !(strict && is_line_ending(*c)))
+            else {
+                break;
+            };
+            if c == '$' && self.options.interpolation && self.peek_next() == Some('{') {
+                token_type = TokenType::INTERPOLATION;
+                break;
+            }
+            let column = self.column;
+            let offset = self.current;
+            self.advance();
+            match c {
+                '\\' => {
+                    let value = value
+                        .get_or_insert_with(|| self.source[self.start + 1..offset].to_string());
+                    match self.escape(column) {
+                        Ok(c) => value.push(c),
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+                // line endings in the value are always "\n"
+                '\r' => {
+                    let value = value
+                        .get_or_insert_with(|| self.source[self.start + 1..offset].to_string());
+                    if self.peek() != Some('\n') {
+                        value.push('\n');
+                    }
+                }
+                _ => {
+                    if let Some(value) = value.as_mut() {
+                        value.push(c);
+                    }
+                }
+            }
+        }
+        // in strict mode the string stops short at a raw newline
+        if self.is_at_end() || self.peek().is_some_and(is_line_ending) {
+            return Err(LoxError::ParseError {
+                line: self.start_line,
+                column: self.start_column,
+                message: String::from("Unterminated string."),
+            });
+        }
+        let delimiter_len = if token_type == TokenType::INTERPOLATION {
+            self.interpolations.push(Interpolation {
+                quote,
+                depth: 0,
+                line: self.line,
+                column: self.column,
+            });
+            // The "${".
+            self.advance();
+            self.advance();
+            2
+        } else {
+            // The closing quote.
+            self.advance();
+            1
+        };
+        // the limit counts the delimiters too
+        self.check_string_length()?;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        // the literal is the unquoted, unescaped value; the delimiters are
+        // ASCII, so trimming them always lands on a char boundary
+        let value = match value {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.source[self.start + 1..self.current - delimiter_len]),
+        };
+        self.add_token_with_literal(token_type, Literal::Str(value));
+        Ok(())
+    }
+
+    // Scans r"..." where backslashes are literal; only the quote ends it.
+    fn raw_string(&mut self) -> Result<(), LoxError> {
+        // The opening quote.
+        let quote = self.advance();
+        let strict = self.options.strict_newlines;
+        while self
+            .peek()
+            .filter(|c| Some(*c) != quote && !(strict && is_line_ending(*c)))
+            .is_some()
+        {
+            self.advance();
+            self.check_string_length()?;
+        }
+        if self.is_at_end() || self.peek().is_some_and(is_line_ending) {
+            return Err(LoxError::ParseError {
+                line: self.start_line,
+                column: self.start_column,
+                message: String::from("Unterminated string."),
+            });
+        }
+        // The closing quote.
+        self.advance();
+        self.check_string_length()?;
+        let value = &self.source[self.start + 2..self.current - 1];
+        let value = if value.contains('\r') {
+            Cow::Owned(value.replace("\r\n", "\n").replace('\r', "\n"))
+        } else {
+            Cow::Borrowed(value)
+        };
+        self.add_token_with_literal(TokenType::STRING, Literal::Str(value));
+        Ok(())
+    }
+
+    fn check_string_length(&mut self) -> Result<(), LoxError> {
+        match self.limits.max_string_length {
+            Some(max) if self.current - self.start > max => Err(self.literal_too_long(format!(
+                "String literal is longer than the limit of {max} bytes."
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_number_length(&mut self) -> Result<(), LoxError> {
+        match self.limits.max_number_length {
+            Some(max) if self.current - self.start > max => Err(self.literal_too_long(format!(
+                "Number literal is longer than the limit of {max} bytes."
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // More input could only make the literal longer, so a stream need not
+    // read any further to be sure of the error.
+    fn literal_too_long(&mut self, message: String) -> LoxError {
+        self.starved.set(false);
+        self.limit_exceeded(message)
+    }
+
+    fn escape(&mut self, column: usize) -> Result<char, LoxError> {
+        let line = self.line;
+        let c = match self.advance() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('$') => '$',
+            Some('u') => return self.unicode_escape(column),
+            Some(c) => {
+                return Err(LoxError::ParseError {
+                    line,
+                    column,
+                    message: format!("Invalid escape sequence '\\{}'.", c.escape_debug()),
+                });
+            }
+            None => {
+                return Err(LoxError::ParseError {
+                    line: self.start_line,
+                    column: self.start_column,
+                    message: String::from("Unterminated string."),
+                })
+            }
+        };
+        Ok(c)
+    }
+
+    // parses the "{XXXX}" part of a "\u{XXXX}" escape, with 1 to 6 hex digits
+    fn unicode_escape(&mut self, column: usize) -> Result<char, LoxError> {
+        let mut text = String::from("\\u");
+        let mut digits = String::new();
+        let closed = if self.match_char('{') {
+            text.push('{');
+            while let Some(c) = self.peek().filter(|c| c.is_ascii_hexdigit()) {
+                self.advance();
+                text.push(c);
+                digits.push(c);
+            }
+            self.match_char('}')
+        } else {
+            false
+        };
+        if closed {
+            text.push('}');
+        }
+
+        let c = Some(digits)
+            .filter(|digits| closed && (1..=6).contains(&digits.len()))
+            .and_then(|digits| u32::from_str_radix(&digits, 16).ok())
+            .and_then(char::from_u32);
+        c.ok_or_else(|| LoxError::ParseError {
+            line: self.line,
+            column,
+            message: format!("Invalid unicode escape '{text}'."),
+        })
+    }
+
+    // "\r\n" and a lone '\r' end a line just like '\n' does
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.current += c.len_utf8();
+        match c {
+            // counted at the '\n' that follows
+            '\r' if self.peek() == Some('\n') => {}
+            '\n' | '\r' => {
+                self.line += 1;
+                self.column = 1;
+            }
+            _ => self.column += 1,
+        }
+        Some(c)
+    }
+
+    fn add_comment_token(&mut self) {
+        let text = &self.source[self.start..self.current];
+        self.comment_chars += text.chars().count();
+        match text.strip_prefix("///") {
+            // one space after the slashes is part of the marker, not the text
+            Some(doc) if self.options.doc_comments => {
+                let doc = doc.strip_prefix(' ').unwrap_or(doc);
+                self.add_token_with_literal(
+                    TokenType::DOC_COMMENT,
+                    Literal::Str(Cow::Borrowed(doc)),
+                );
+            }
+            _ if self.options.comment_tokens => self.add_token(TokenType::COMMENT),
+            _ => {}
+        }
+    }
+
+    fn add_token(&mut self, token_type: TokenType) {
+        self.push_token(token_type, None);
+    }
+
+    fn add_token_with_literal(&mut self, token_type: TokenType, literal: Literal<'a>) {
+        self.push_token(token_type, Some(literal));
+    }
+
+    fn push_token(&mut self, token_type: TokenType, literal: Option<Literal<'a>>) {
+        self.tokens += 1;
+        if let Some(max) = self.limits.max_tokens {
+            if self.tokens > max {
+                let error = self.limit_exceeded(format!("More tokens than the limit of {max}."));
+                self.pending.push_back(Err(error));
+                return;
+            }
+        }
+        if !matches!(token_type, TokenType::COMMENT | TokenType::DOC_COMMENT) {
+            self.previous = Some(token_type);
+        }
+        self.pending.push_back(Ok(Token {
+            token_type,
+            lexeme: Cow::Borrowed(&self.source[self.start..self.current]),
+            literal,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.offset + self.start..self.offset + self.current,
+        }));
+    }
+
+    // position bookkeeping is left entirely to advance()
+    fn match_char(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
             self.advance();
             true
         } else {
@@ -335,3 +1573,1886 @@ impl Scanner<'_> {
         }
     }
 }
+
+// Scans a stream a chunk at a time. Each token is scanned from a buffer
+// holding what is left of the input read so far; if scanning it needed to
+// look past the end of the buffer, more input is read and it is scanned again.
+pub struct StreamScanner<R> {
+    reader: R,
+    options: ScannerOptions,
+    limits: ScannerLimits,
+    buffer: String,
+    // byte offset in the buffer of the next token
+    position: usize,
+    // the start of a character split between two reads
+    undecoded: Vec<u8>,
+    state: ScanState,
+    pending: VecDeque<Result<Token<'static>, LoxError>>,
+    eof: bool,
+    // a failed read ends the input, and is reported just before EOF
+    read_error: Option<LoxError>,
+}
+
+impl<R: BufRead> Iterator for StreamScanner<R> {
+    type Item = Result<Token<'static>, LoxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+impl<R: BufRead> StreamScanner<R> {
+    pub fn scan_tokens(mut self) -> Result<Vec<Token<'static>>, Vec<LoxError>> {
+        self.scan_all()
+    }
+
+    pub fn scan_tokens_with_stats(
+        mut self,
+    ) -> (Result<Vec<Token<'static>>, Vec<LoxError>>, ScanStats) {
+        let mut stats = ScanStats::default();
+        let result = collect_tokens(self.by_ref().inspect(|result| {
+            if let Ok(token) = result {
+                stats.record(token);
+            }
+        }));
+        let state = &self.state;
+        stats.finish(state.line, state.column, state.comment_chars);
+        (result, stats)
+    }
+
+    pub fn scan_all(&mut self) -> Result<Vec<Token<'static>>, Vec<LoxError>> {
+        collect_tokens(self.by_ref())
+    }
+
+    pub fn scan_with(
+        &mut self,
+        f: impl FnMut(Token<'static>) -> ControlFlow<()>,
+    ) -> Result<(), Vec<LoxError>> {
+        sink_tokens(self.by_ref(), f)
+    }
+
+    pub fn next_token(&mut self) -> Option<Result<Token<'static>, LoxError>> {
+        loop {
+            if let Some(result) = self.pending.pop_front() {
+                return Some(result);
+            }
+            let source = &self.buffer[self.position..];
+            let state = self.state.clone();
+            let mut scanner = Scanner::resume(source, self.options.clone(), self.limits, state);
+            let result = scanner.next_token();
+            if scanner.starved.get() && !self.eof {
+                // at least double the buffer, so a long token is rescanned
+                // only a logarithmic number of times
+                let wanted = 2 * source.len();
+                while !self.eof && self.buffer.len() - self.position < wanted.max(1) {
+                    if let Err(error) = self.fill() {
+                        self.eof = true;
+                        self.read_error = Some(error);
+                    }
+                }
+                continue;
+            }
+            for result in result.into_iter().chain(scanner.pending.drain(..)) {
+                if matches!(&result, Ok(token) if token.token_type == TokenType::EOF) {
+                    self.pending.extend(self.read_error.take().map(Err));
+                }
+                self.pending.push_back(result.map(Token::into_owned));
+            }
+            self.position += scanner.current;
+            self.state = scanner.suspend();
+            if self.pending.is_empty() {
+                return None;
+            }
+        }
+    }
+
+    // Reads the next chunk of input onto the buffer, dropping the part of it
+    // that has already been scanned.
+    fn fill(&mut self) -> Result<(), LoxError> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        let chunk = self.reader.fill_buf().map_err(io_error)?;
+        if chunk.is_empty() {
+            self.eof = true;
+            return if self.undecoded.is_empty() {
+                Ok(())
+            } else {
+                Err(io_error(invalid_utf8()))
+            };
+        }
+        self.undecoded.extend_from_slice(chunk);
+        let len = chunk.len();
+        self.reader.consume(len);
+        // a character cut off at the end of the chunk is decoded next time
+        let (valid, invalid) = match str::from_utf8(&self.undecoded) {
+            Ok(text) => (text.len(), false),
+            Err(error) => (error.valid_up_to(), error.error_len().is_some()),
+        };
+        let text = str::from_utf8(&self.undecoded[..valid]).expect("prefix is valid UTF-8");
+        self.buffer.push_str(text);
+        self.undecoded.drain(..valid);
+        if let Some(max) = self.limits.max_source_bytes {
+            // cut short exactly where Scanner::with_limits would be
+            let read = self.state.offset + self.buffer.len();
+            if read > max {
+                let end = self.buffer.floor_char_boundary(max - self.state.offset);
+                self.buffer.truncate(end);
+                self.state.truncated = true;
+                self.eof = true;
+                return Ok(());
+            }
+        }
+        if invalid {
+            return Err(io_error(invalid_utf8()));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
+fn io_error(error: io::Error) -> LoxError {
+    LoxError::IoError {
+        message: error.to_string(),
+    }
+}
+
+// Warns about identifiers that are keywords in the wrong case, like `If` or
+// `NIL`, which would otherwise only fail later as undefined variables. Only
+// the keywords `options` enable count; without in_keyword, `IN` is a name.
+pub fn keyword_case_warnings(tokens: &[Token], options: &ScannerOptions) -> Vec<LoxError> {
+    tokens
+        .iter()
+        .filter(|token| token.token_type == TokenType::IDENTIFIER)
+        .filter_map(|token| {
+            let keyword = options.keyword(&token.lexeme.to_ascii_lowercase())?;
+            Some(LoxError::Warning {
+                line: token.line,
+                column: token.column,
+                message: format!(
+                    "'{}' is an identifier, not a keyword; did you mean `{}`?",
+                    token.lexeme,
+                    keyword.keyword_str()?
+                ),
+            })
+        })
+        .collect()
+}
+
+fn is_line_ending(c: char) -> bool {
+    matches!(c, '\n' | '\r')
+}
+
+fn collect_tokens<'a>(
+    results: impl Iterator<Item = Result<Token<'a>, LoxError>>,
+) -> Result<Vec<Token<'a>>, Vec<LoxError>> {
+    let mut tokens = Vec::new();
+    sink_tokens(results, |token| {
+        tokens.push(token);
+        ControlFlow::Continue(())
+    })?;
+    Ok(tokens)
+}
+
+fn sink_tokens<'a>(
+    results: impl Iterator<Item = Result<Token<'a>, LoxError>>,
+    mut f: impl FnMut(Token<'a>) -> ControlFlow<()>,
+) -> Result<(), Vec<LoxError>> {
+    let mut errors = Vec::new();
+    // keep scanning so every lexical error gets reported
+    for result in results {
+        match result {
+            Ok(token) => {
+                if f(token).is_break() {
+                    break;
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// Maps the lines and columns in tokens and errors back onto the source for
+// people to read. Columns count a tab as one character, which says nothing
+// about where it lands on screen, so the caret is placed with tabs expanded
+// to tab stops every `tab_width` columns.
+pub struct SourceMap<'a> {
+    source: &'a str,
+    tab_width: usize,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: &'a str) -> Self {
+        SourceMap::with_tab_width(source, 8)
+    }
+
+    pub fn with_tab_width(source: &'a str, tab_width: usize) -> Self {
+        SourceMap {
+            // columns on the first line don't count the byte order mark
+            source: source.strip_prefix('\u{feff}').unwrap_or(source),
+            tab_width: tab_width.max(1),
+        }
+    }
+
+    // the text of a 1-based line, without its line ending, which may be
+    // "\n", "\r\n" or '\r' as for the scanner
+    pub fn line(&self, line: usize) -> Option<&'a str> {
+        let mut rest = self.source;
+        for _ in 1..line {
+            let end = rest.find(is_line_ending)?;
+            let ending = if rest[end..].starts_with("\r\n") {
+                2
+            } else {
+                1
+            };
+            rest = &rest[end + ending..];
+        }
+        Some(&rest[..rest.find(is_line_ending).unwrap_or(rest.len())])
+    }
+
+    // The 1-based screen column of a character column; columns past the end
+    // of the line carry on one per character.
+    pub fn display_column(&self, line: usize, column: usize) -> Option<usize> {
+        let mut chars = self.line(line)?.chars();
+        let width = (1..column).fold(0, |width, _| self.advance(width, chars.next()));
+        Some(width + 1)
+    }
+
+    // The line, tabs expanded, with a caret under the column on the next.
+    pub fn caret(&self, line: usize, column: usize) -> Option<String> {
+        let mut expanded = String::new();
+        let mut width = 0;
+        for c in self.line(line)?.chars() {
+            let next = self.advance(width, Some(c));
+            match c {
+                '\t' => expanded.extend(iter::repeat_n(' ', next - width)),
+                _ => expanded.push(c),
+            }
+            width = next;
+        }
+        let indent = " ".repeat(self.display_column(line, column)? - 1);
+        Some(format!("{expanded}\n{indent}^"))
+    }
+
+    // the screen width after showing `c` at `width`
+    fn advance(&self, width: usize, c: Option<char>) -> usize {
+        match c {
+            Some('\t') => (width / self.tab_width + 1) * self.tab_width,
+            _ => width + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Vec<Token<'_>> {
+        Scanner::new(source).scan_tokens().unwrap()
+    }
+
+    fn types(source: &str) -> Vec<TokenType> {
+        scan(source).iter().map(|token| token.token_type).collect()
+    }
+
+    // the line and message of each error scanning `source` reports
+    fn errors(source: &str) -> Vec<(usize, String)> {
+        Scanner::new(source)
+            .scan_tokens()
+            .unwrap_err()
+            .into_iter()
+            .map(|error| match error {
+                LoxError::ParseError { line, message, .. } => (line, message),
+                error => panic!("unexpected {error:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn block_comment_spanning_lines() {
+        let tokens = scan("/* one\ntwo\nthree */ x");
+        assert_eq!(tokens[0].token_type, TokenType::IDENTIFIER);
+        assert_eq!(tokens[0].line, 3);
+    }
+
+    #[test]
+    fn block_comment_containing_slashes_and_stars() {
+        use TokenType::*;
+        assert_eq!(types("/* a / b * c ** // / */ 1"), [NUMBER, EOF]);
+        assert_eq!(types("1 /***/ / /**/ 2"), [NUMBER, SLASH, NUMBER, EOF]);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        assert_eq!(
+            errors("x /* never closed"),
+            [(1, String::from("Unterminated block comment."))]
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        use TokenType::*;
+        assert_eq!(
+            types("/* outer /* inner */ still comment */ 1"),
+            [NUMBER, EOF]
+        );
+        assert_eq!(types("/* 1 /* 2 /* 3 */ 2 */ 1 */ x"), [IDENTIFIER, EOF]);
+    }
+
+    #[test]
+    fn stray_comment_close_is_operators() {
+        use TokenType::*;
+        assert_eq!(types("a */ b"), [IDENTIFIER, STAR, SLASH, IDENTIFIER, EOF]);
+    }
+
+    #[test]
+    fn unbalanced_nesting_reports_outermost_start() {
+        assert_eq!(
+            errors("x\n/* outer\n/* inner */\n"),
+            [(2, String::from("Unterminated block comment."))]
+        );
+    }
+
+    #[test]
+    fn unterminated_comment_reports_opening_line() {
+        let mut source = String::from("var a = 1;\nvar b = 2;\n/* opened here\n");
+        for line in 4..=50 {
+            source.push_str(&format!("print {line};\n"));
+        }
+        assert_eq!(source.lines().count(), 50);
+        assert_eq!(
+            errors(&source),
+            [(3, String::from("Unterminated block comment."))]
+        );
+    }
+
+    fn string_value(source: &str) -> String {
+        match &scan(source)[0].literal {
+            Some(Literal::Str(s)) => s.to_string(),
+            literal => panic!("no string in {literal:?}"),
+        }
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert_eq!(string_value(r#""\u{41}\u{7a}""#), "Az");
+        assert_eq!(string_value(r#""caf\u{e9} \u{20AC}""#), "café €");
+        assert_eq!(string_value(r#""\u{1F600}""#), "\u{1F600}");
+    }
+
+    #[test]
+    fn invalid_unicode_escapes() {
+        for (source, escape) in [
+            (r#""\u{D800}""#, r"\u{D800}"),
+            (r#""\u{110000}""#, r"\u{110000}"),
+            (r#""\u{1234567}""#, r"\u{1234567}"),
+            (r#""\u{41""#, r"\u{41"),
+            (r#""\u{}""#, r"\u{}"),
+            (r#""\u41""#, r"\u"),
+        ] {
+            let message = format!("Invalid unicode escape '{escape}'.");
+            assert_eq!(errors(source), [(1, message)], "{source}");
+        }
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let source = "var a = 1 @ 2;\nprint a;\nvar b = a ` 3;\nprint \"never closed;\n";
+        let reported = errors(source);
+        let lines: Vec<_> = reported.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 3, 4]);
+        assert!(reported[0].1.starts_with("Unexpected character '@'"));
+        assert!(reported[1].1.starts_with("Unexpected character '`'"));
+        assert_eq!(reported[2].1, "Unterminated string.");
+    }
+
+    fn positions(source: &str) -> Vec<(&str, usize, usize)> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        tokens
+            .into_iter()
+            .map(|token| match token.lexeme {
+                Cow::Borrowed(lexeme) => (lexeme, token.line, token.column),
+                Cow::Owned(_) => panic!("lexeme not borrowed"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn columns_after_tabs() {
+        // a tab is one character, wherever it takes the cursor
+        assert_eq!(
+            positions("\tvar\t\tx = 1;"),
+            [
+                ("var", 1, 2),
+                ("x", 1, 7),
+                ("=", 1, 9),
+                ("1", 1, 11),
+                (";", 1, 12),
+                ("", 1, 13)
+            ]
+        );
+    }
+
+    #[test]
+    fn columns_after_comments() {
+        assert_eq!(
+            positions("/* é */ a // b\n  c"),
+            [("a", 1, 9), ("c", 2, 3), ("", 2, 4)]
+        );
+    }
+
+    #[test]
+    fn columns_after_multiline_string() {
+        assert_eq!(
+            positions("x = \"one\ntwo\" + y;\n z"),
+            [
+                ("x", 1, 1),
+                ("=", 1, 3),
+                ("\"one\ntwo\"", 1, 5),
+                ("+", 2, 6),
+                ("y", 2, 8),
+                (";", 2, 9),
+                ("z", 3, 2),
+                ("", 3, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_slice_lexemes_from_the_source() {
+        let source = "var café = \"naïve ☕\";\nprint café + 'ü';";
+        let options = ScannerOptions::default()
+            .unicode_identifiers(true)
+            .single_quotes(true);
+        let tokens = Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(tokens[1].lexeme, "café");
+        for token in &tokens {
+            assert_eq!(&source[token.span.clone()], token.lexeme);
+        }
+        assert_eq!(tokens.last().unwrap().span, source.len()..source.len());
+    }
+
+    #[test]
+    fn iterator_matches_scan_tokens() {
+        let source = "fun f(a) { return a * 2; } // done";
+        let mut scanner = Scanner::new(source);
+        let mut tokens = Vec::new();
+        for result in scanner.by_ref() {
+            tokens.push(result.unwrap());
+        }
+        assert_eq!(tokens, scan(source));
+        assert!(scanner.next().is_none());
+        assert!(scanner.next_token().is_none());
+    }
+
+    #[test]
+    fn iterator_recovers_after_errors() {
+        use TokenType::*;
+        let results: Vec<_> = Scanner::new("a @ b").collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().token_type, IDENTIFIER);
+        assert!(matches!(
+            results[1],
+            Err(LoxError::ParseError { column: 3, .. })
+        ));
+        assert_eq!(results[2].as_ref().unwrap().lexeme, "b");
+        assert_eq!(results[3].as_ref().unwrap().token_type, EOF);
+    }
+
+    #[test]
+    fn eof_is_yielded_once() {
+        let eofs = Scanner::new("1 2")
+            .filter(|result| matches!(result, Ok(token) if token.token_type == TokenType::EOF))
+            .count();
+        assert_eq!(eofs, 1);
+    }
+
+    #[test]
+    fn lexemes_borrow_from_a_large_source() {
+        let mut source = String::new();
+        for i in 0..500 {
+            source.push_str(&format!("var v{i} = \"s{i}\" + {i}.5; // c\n"));
+        }
+        let tokens = scan(&source);
+        assert_eq!(tokens.len(), 500 * 7 + 1);
+        for token in &tokens {
+            assert!(matches!(token.lexeme, Cow::Borrowed(_)));
+            assert_eq!(&source[token.span.clone()], token.lexeme);
+        }
+        assert_eq!(tokens[7 * 499 + 1].lexeme, "v499");
+    }
+
+    #[test]
+    fn literals_apart_from_lexemes() {
+        let tokens = scan("12.5 7 \"hi there\" \"\" x");
+        assert_eq!(tokens[0].lexeme, "12.5");
+        assert_eq!(tokens[0].literal, Some(Literal::Number(12.5)));
+        assert_eq!(tokens[1].literal, Some(Literal::Number(7.0)));
+        assert_eq!(tokens[2].lexeme, "\"hi there\"");
+        assert_eq!(
+            tokens[2].literal,
+            Some(Literal::Str(Cow::Borrowed("hi there")))
+        );
+        assert_eq!(tokens[3].literal, Some(Literal::Str(Cow::Borrowed(""))));
+        assert_eq!(tokens[4].literal, None);
+    }
+
+    #[test]
+    fn token_comparisons() {
+        let tokens = scan("a b a 1 2");
+        assert_eq!(tokens[0].token_type, tokens[1].token_type);
+        assert_ne!(tokens[0], tokens[1]);
+        // the same name at another place is another token
+        assert_ne!(tokens[0], tokens[2]);
+        assert_eq!(tokens[0].clone(), tokens[0]);
+        assert_eq!(tokens[3].token_type, tokens[4].token_type);
+        assert_ne!(tokens[3].literal, tokens[4].literal);
+
+        let counts = tokens.iter().fold(HashMap::new(), |mut counts, token| {
+            *counts.entry(token.token_type).or_insert(0) += 1;
+            counts
+        });
+        assert_eq!(counts[&TokenType::IDENTIFIER], 3);
+        assert_eq!(counts[&TokenType::NUMBER], 2);
+    }
+
+    #[test]
+    fn tokens_display_as_jlox_does() {
+        let tokens = scan("var pi = 3.14; print \"hi\" + 12 >= x;");
+        let lines: Vec<_> = tokens.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "VAR var null",
+                "IDENTIFIER pi null",
+                "EQUAL = null",
+                "NUMBER 3.14 3.14",
+                "SEMICOLON ; null",
+                "PRINT print null",
+                "STRING \"hi\" hi",
+                "PLUS + null",
+                "NUMBER 12 12.0",
+                "GREATER_EQUAL >= null",
+                "IDENTIFIER x null",
+                "SEMICOLON ; null",
+                "EOF  null",
+            ]
+        );
+    }
+
+    fn number_with(source: &str, options: ScannerOptions) -> f64 {
+        let tokens = Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap();
+        match tokens[..] {
+            [Token {
+                literal: Some(Literal::Number(n)),
+                ..
+            }, _] => n,
+            _ => panic!("{source} is not one number: {tokens:?}"),
+        }
+    }
+
+    fn errors_with(source: &str, options: ScannerOptions) -> Vec<(usize, String)> {
+        Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap_err()
+            .into_iter()
+            .map(|error| match error {
+                LoxError::ParseError { line, message, .. } => (line, message),
+                error => panic!("unexpected {error:?}"),
+            })
+            .collect()
+    }
+
+    fn hex() -> ScannerOptions {
+        ScannerOptions::default().hex_literals(true)
+    }
+
+    #[test]
+    fn hex_literals() {
+        assert_eq!(number_with("0x0", hex()), 0.0);
+        assert_eq!(number_with("0xdeadBEEF", hex()), 3735928559.0);
+        assert_eq!(number_with("0XFF", hex()), 255.0);
+    }
+
+    #[test]
+    fn invalid_hex_literals() {
+        assert_eq!(
+            errors_with("\n0x", hex()),
+            [(2, String::from("Invalid hexadecimal literal '0x'."))]
+        );
+        assert_eq!(
+            errors_with("0xG", hex()),
+            [(1, String::from("Invalid hexadecimal literal '0xG'."))]
+        );
+    }
+
+    #[test]
+    fn long_hex_literals_round_once() {
+        // each digit rounded on its own would come out an ulp low
+        let exact = 0x2459b6297b324d68d242f9_u128;
+        assert_eq!(number_with("0x2459b6297b324d68d242f9", hex()), exact as f64);
+        // 2^53 + 1 is halfway between two f64s and rounds to even
+        assert_eq!(number_with("0x20000000000001", hex()), 2f64.powi(53));
+        // but a one beyond what a u128 holds puts it past halfway
+        let source = format!("0x20000000000001{}1", "0".repeat(24));
+        let rounded_up = (2f64.powi(53) + 2.0) * 2f64.powi(100);
+        assert_eq!(number_with(&source, hex()), rounded_up);
+        let source = format!("0x20000000000001{}", "0".repeat(25));
+        assert_eq!(number_with(&source, hex()), 2f64.powi(153));
+    }
+
+    #[test]
+    fn binary_and_octal_literals() {
+        assert_eq!(number_with("0b1010", hex()), 10.0);
+        assert_eq!(number_with("0B1111_0000", hex()), 240.0);
+        assert_eq!(number_with("0o755", hex()), 493.0);
+        assert_eq!(number_with("0O1_7", hex()), 15.0);
+    }
+
+    #[test]
+    fn invalid_binary_and_octal_literals() {
+        for (source, message) in [
+            ("0b", "Invalid binary literal '0b'."),
+            ("0b102", "Invalid binary literal '0b102'."),
+            ("0o", "Invalid octal literal '0o'."),
+            ("0o78", "Invalid octal literal '0o78'."),
+            ("0b1__0", "Invalid binary literal '0b1__0'."),
+            ("0o7_", "Invalid octal literal '0o7_'."),
+            ("0b_1", "Invalid binary literal '0b_1'."),
+        ] {
+            assert_eq!(
+                errors_with(source, hex()),
+                [(1, String::from(message))],
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn radix_literal_running_into_a_name() {
+        // rather than a number and then an identifier
+        assert_eq!(
+            errors_with("0b101abc", hex()),
+            [(1, String::from("Invalid binary literal '0b101abc'."))]
+        );
+        assert_eq!(
+            errors_with("0o17z", hex()),
+            [(1, String::from("Invalid octal literal '0o17z'."))]
+        );
+    }
+
+    fn number(source: &str) -> f64 {
+        number_with(source, ScannerOptions::default())
+    }
+
+    #[test]
+    fn exponents() {
+        assert_eq!(number("1e9"), 1e9);
+        assert_eq!(number("1E+6"), 1e6);
+        assert_eq!(number("2.5e-3"), 2.5e-3);
+        assert_eq!(number("12.75E2"), 1275.0);
+    }
+
+    #[test]
+    fn dangling_exponent_is_an_identifier() {
+        use TokenType::*;
+        assert_eq!(types("1e"), [NUMBER, IDENTIFIER, EOF]);
+        assert_eq!(types("1e+"), [NUMBER, IDENTIFIER, PLUS, EOF]);
+        assert_eq!(types("1e-x"), [NUMBER, IDENTIFIER, MINUS, IDENTIFIER, EOF]);
+        assert_eq!(scan("2.5e+")[0].lexeme, "2.5");
+    }
+
+    #[test]
+    fn digit_separators() {
+        let tokens = scan("1_000_000_000 3_141.592_65");
+        assert_eq!(tokens[0].lexeme, "1_000_000_000");
+        assert_eq!(tokens[0].literal, Some(Literal::Number(1e9)));
+        assert_eq!(tokens[1].literal, Some(Literal::Number(3141.59265)));
+    }
+
+    #[test]
+    fn misplaced_digit_separators() {
+        use TokenType::*;
+        for source in ["1__0", "1_", "1_.5", "1.5_", "1e5_"] {
+            let message = format!("Invalid number literal '{source}'.");
+            assert_eq!(errors(source), [(1, message)], "{source}");
+        }
+        // a leading underscore starts a name, as it does after a dot or an
+        // exponent marker
+        assert_eq!(types("_1"), [IDENTIFIER, EOF]);
+        assert_eq!(types("1._5"), [NUMBER, DOT, IDENTIFIER, EOF]);
+        assert_eq!(types("1e_5"), [NUMBER, IDENTIFIER, EOF]);
+    }
+
+    #[test]
+    fn percent() {
+        use TokenType::*;
+        assert_eq!(types("a % b"), [IDENTIFIER, PERCENT, IDENTIFIER, EOF]);
+    }
+
+    #[test]
+    fn ternary_tokens() {
+        use TokenType::*;
+        let tokens: Vec<_> = scan("a ? b : c")
+            .into_iter()
+            .map(|token| (token.token_type, token.lexeme))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (IDENTIFIER, "a".into()),
+                (QUESTION, "?".into()),
+                (IDENTIFIER, "b".into()),
+                (COLON, ":".into()),
+                (IDENTIFIER, "c".into()),
+                (EOF, "".into()),
+            ]
+        );
+    }
+
+    fn interpolating() -> ScannerOptions {
+        ScannerOptions::default().interpolation(true)
+    }
+
+    // the type of each token, with the value of any string
+    fn string_parts(source: &str) -> Vec<(TokenType, Option<String>)> {
+        Scanner::with_options(source, interpolating())
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .map(|token| match token.literal {
+                Some(Literal::Str(s)) => (token.token_type, Some(s.to_string())),
+                _ => (token.token_type, None),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interpolation() {
+        use TokenType::*;
+        let part = |token_type, s: &str| (token_type, Some(s.to_string()));
+        assert_eq!(
+            string_parts(r#""plain $ {}""#),
+            [part(STRING, "plain $ {}"), (EOF, None)]
+        );
+        assert_eq!(
+            string_parts(r#""sum is ${a + b}.""#),
+            [
+                part(INTERPOLATION, "sum is "),
+                (IDENTIFIER, None),
+                (PLUS, None),
+                (IDENTIFIER, None),
+                part(STRING, "."),
+                (EOF, None),
+            ]
+        );
+        assert_eq!(
+            string_parts(r#""${a}, ${b}""#),
+            [
+                part(INTERPOLATION, ""),
+                (IDENTIFIER, None),
+                part(INTERPOLATION, ", "),
+                (IDENTIFIER, None),
+                part(STRING, ""),
+                (EOF, None),
+            ]
+        );
+        assert_eq!(
+            string_parts(r#""a ${"b" + "${c}"} d""#),
+            [
+                part(INTERPOLATION, "a "),
+                part(STRING, "b"),
+                (PLUS, None),
+                part(INTERPOLATION, ""),
+                (IDENTIFIER, None),
+                part(STRING, ""),
+                part(STRING, " d"),
+                (EOF, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        assert_eq!(
+            errors_with("print \"a\n${b + c;\nprint d;", interpolating()),
+            [(2, String::from("Unterminated string interpolation."))]
+        );
+    }
+
+    #[test]
+    fn interpolation_is_opt_in() {
+        assert_eq!(string_value(r#""a ${b} c""#), "a ${b} c");
+        assert_eq!(string_value(r#""${""#), "${");
+    }
+
+    fn identifiers_with(source: &str, options: ScannerOptions) -> Vec<(String, Range<usize>)> {
+        Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .filter(|token| token.token_type == TokenType::IDENTIFIER)
+            .map(|token| (token.lexeme.to_string(), token.span))
+            .collect()
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let unicode = ScannerOptions::default().unicode_identifiers(true);
+        assert_eq!(
+            identifiers_with("variável числа 变量2 _ñ", unicode.clone()),
+            [
+                (String::from("variável"), 0..9),
+                (String::from("числа"), 10..20),
+                (String::from("变量2"), 21..28),
+                (String::from("_ñ"), 29..32),
+            ]
+        );
+        // keywords are only ever ASCII
+        assert_eq!(
+            identifiers_with("ａnd", unicode.clone()),
+            [(String::from("ａnd"), 0..5)]
+        );
+        assert_eq!(
+            errors_with("var 🦀 = 1;", unicode),
+            [(1, String::from("Unexpected character '🦀' (U+1F980)."))]
+        );
+        assert_eq!(
+            errors_with(
+                "variável",
+                ScannerOptions::default().unicode_identifiers(false)
+            ),
+            [(1, String::from("Unexpected character 'á' (U+00E1)."))]
+        );
+    }
+
+    #[test]
+    fn comment_tokens() {
+        use TokenType::*;
+        let source = "// first\nvar a /* inline */ = 1; // trailing\n/* last */";
+        let tokens: Vec<_> =
+            Scanner::with_options(source, ScannerOptions::default().comment_tokens(true))
+                .scan_tokens()
+                .unwrap()
+                .into_iter()
+                .map(|token| (token.token_type, token.lexeme, token.line))
+                .collect();
+        assert_eq!(
+            tokens,
+            [
+                (COMMENT, "// first".into(), 1),
+                (VAR, "var".into(), 2),
+                (IDENTIFIER, "a".into(), 2),
+                (COMMENT, "/* inline */".into(), 2),
+                (EQUAL, "=".into(), 2),
+                (NUMBER, "1".into(), 2),
+                (SEMICOLON, ";".into(), 2),
+                (COMMENT, "// trailing".into(), 2),
+                (COMMENT, "/* last */".into(), 3),
+                (EOF, "".into(), 3),
+            ]
+        );
+        // and by default they are skipped
+        assert_eq!(
+            types(source),
+            [VAR, IDENTIFIER, EQUAL, NUMBER, SEMICOLON, EOF]
+        );
+    }
+
+    #[test]
+    fn shebang() {
+        let script = "print 1;\nvar a = \"b\";";
+        let strip = |tokens: Vec<Token>| -> Vec<_> {
+            tokens
+                .into_iter()
+                .map(|token| (token.token_type, token.line))
+                .collect()
+        };
+        assert_eq!(
+            strip(scan(&format!("#!/usr/bin/env rlox\n{script}"))),
+            strip(scan(&format!("\n{script}")))
+        );
+        assert_eq!(
+            errors("print 1;\n#!/usr/bin/env rlox"),
+            [(2, String::from("Unexpected character '#' (U+0023)."))]
+        );
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let tokens = scan("\u{feff}print 1;");
+        assert_eq!(tokens[0].token_type, TokenType::PRINT);
+        assert_eq!((tokens[0].column, tokens[0].span.clone()), (1, 3..8));
+        // a shebang may follow it
+        assert_eq!(
+            types("\u{feff}#!rlox\nnil"),
+            [TokenType::NIL, TokenType::EOF]
+        );
+        assert_eq!(
+            errors("print \u{feff}1;"),
+            [(
+                1,
+                String::from(r"Unexpected character '\u{feff}' (U+FEFF).")
+            )]
+        );
+    }
+
+    #[test]
+    fn unexpected_character_names_it() {
+        let message = |source| errors(source).remove(0).1;
+        assert_eq!(message("print “hi;"), "Unexpected character '“' (U+201C).");
+        assert_eq!(
+            message("var a\u{200b} = 1;"),
+            r"Unexpected character '\u{200b}' (U+200B)."
+        );
+        assert_eq!(
+            message("a \u{7} b"),
+            r"Unexpected character '\u{7}' (U+0007)."
+        );
+    }
+
+    #[test]
+    fn unexpected_character_runs() {
+        assert_eq!(
+            errors("print @@@@@;"),
+            [(1, String::from("Unexpected characters \"@@@@@\"."))]
+        );
+        assert_eq!(
+            errors("a @@\n@@@ b"),
+            [
+                (1, String::from("Unexpected characters \"@@\".")),
+                (2, String::from("Unexpected characters \"@@@\".")),
+            ]
+        );
+        // a valid token in between splits the run
+        assert_eq!(
+            errors("@@ + @"),
+            [
+                (1, String::from("Unexpected characters \"@@\".")),
+                (1, String::from("Unexpected character '@' (U+0040).")),
+            ]
+        );
+        assert_eq!(
+            errors("@"),
+            [(1, String::from("Unexpected character '@' (U+0040)."))]
+        );
+    }
+
+    #[test]
+    fn spans_of_one_and_two_character_operators() {
+        let spans: Vec<_> = scan("a==b<=c=d")
+            .into_iter()
+            .map(|token| (token.lexeme, token.span))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("a".into(), 0..1),
+                ("==".into(), 1..3),
+                ("b".into(), 3..4),
+                ("<=".into(), 4..6),
+                ("c".into(), 6..7),
+                ("=".into(), 7..8),
+                ("d".into(), 8..9),
+                ("".into(), 9..9),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_number_is_an_error_not_a_panic() {
+        assert_eq!(
+            errors("print 1;\nprint 1__2 + 3_;"),
+            [
+                (2, String::from("Invalid number literal '1__2'.")),
+                (2, String::from("Invalid number literal '3_'.")),
+            ]
+        );
+    }
+
+    #[test]
+    fn multibyte_strings() {
+        for value in ["héllo", "naïve café", "日本語", "héllo𝄞", "𝄞"] {
+            assert_eq!(string_value(&format!("\"{value}\"")), value);
+        }
+    }
+
+    #[test]
+    fn strings_report_their_opening_line() {
+        let tokens = scan("\n\"one\ntwo\nthree\" four");
+        assert_eq!(tokens[0].token_type, TokenType::STRING);
+        assert_eq!(tokens[0].line, 2);
+        assert_eq!((tokens[1].lexeme.as_ref(), tokens[1].line), ("four", 4));
+    }
+
+    #[test]
+    fn strict_newlines() {
+        let source = "print \"one\ntwo\";\nprint 3;";
+        let permissive = ScannerOptions::default().strict_newlines(false);
+        let strict = ScannerOptions::default().strict_newlines(true);
+        let tokens = Scanner::with_options(source, permissive)
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(tokens[1].literal, Some(Literal::Str("one\ntwo".into())));
+        assert_eq!(
+            errors_with(source, strict.clone()),
+            [
+                (1, String::from("Unterminated string.")),
+                (2, String::from("Unterminated string.")),
+            ]
+        );
+        // an escaped newline is fine either way
+        let escaped = Scanner::with_options(r#""one\ntwo""#, strict)
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(escaped[0].literal, Some(Literal::Str("one\ntwo".into())));
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let unicode = ScannerOptions::default().unicode_identifiers(true);
+        let spans: Vec<_> = Scanner::with_options("é != \"日本\" ab", unicode)
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .map(|token| token.span)
+            .collect();
+        // é is two bytes and each of 日本 three
+        assert_eq!(spans, [0..2, 3..5, 6..14, 15..17, 17..17]);
+    }
+
+    // the type and lexeme of each token before EOF
+    fn lexemes(source: &str) -> Vec<(TokenType, String)> {
+        let mut tokens = scan(source);
+        tokens.pop();
+        tokens
+            .into_iter()
+            .map(|token| (token.token_type, token.lexeme.into_owned()))
+            .collect()
+    }
+
+    fn lexeme(token_type: TokenType, lexeme: &str) -> (TokenType, String) {
+        (token_type, lexeme.to_string())
+    }
+
+    #[test]
+    fn leading_dot_fractions() {
+        use TokenType::*;
+        assert_eq!(number(".5"), 0.5);
+        assert_eq!(number(".25e2"), 25.0);
+        // after an operand the dot is a property access, however odd
+        assert_eq!(
+            lexemes("x.5"),
+            [
+                lexeme(IDENTIFIER, "x"),
+                lexeme(DOT, "."),
+                lexeme(NUMBER, "5")
+            ]
+        );
+        assert_eq!(
+            lexemes(".foo"),
+            [lexeme(DOT, "."), lexeme(IDENTIFIER, "foo")]
+        );
+    }
+
+    #[test]
+    fn trailing_dot_numbers() {
+        use TokenType::*;
+        let expected = String::from("Expected digits after decimal point.");
+        assert_eq!(errors("123."), [(1, expected.clone())]);
+        assert_eq!(errors("f(\n123.)"), [(2, expected)]);
+        assert_eq!(
+            lexemes("123.sqrt()"),
+            [
+                lexeme(NUMBER, "123"),
+                lexeme(DOT, "."),
+                lexeme(IDENTIFIER, "sqrt"),
+                lexeme(LEFT_PAREN, "("),
+                lexeme(RIGHT_PAREN, ")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        use TokenType::*;
+        assert_eq!(
+            types("a & b | c ^ ~d"),
+            [IDENTIFIER, AMP, IDENTIFIER, PIPE, IDENTIFIER, CARET, TILDE, IDENTIFIER, EOF]
+        );
+        assert_eq!(
+            types("a << b < c"),
+            [IDENTIFIER, LESS_LESS, IDENTIFIER, LESS, IDENTIFIER, EOF]
+        );
+        assert_eq!(
+            types("a >> b >= c"),
+            [
+                IDENTIFIER,
+                GREATER_GREATER,
+                IDENTIFIER,
+                GREATER_EQUAL,
+                IDENTIFIER,
+                EOF
+            ]
+        );
+        assert_eq!(
+            types("<<= >>= <<<"),
+            [
+                LESS_LESS,
+                EQUAL,
+                GREATER_GREATER,
+                EQUAL,
+                LESS_LESS,
+                LESS,
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn compound_assignment() {
+        use TokenType::*;
+        assert_eq!(
+            types("a += 1 -= *= %="),
+            [
+                IDENTIFIER,
+                PLUS_EQUAL,
+                NUMBER,
+                MINUS_EQUAL,
+                STAR_EQUAL,
+                PERCENT_EQUAL,
+                EOF
+            ]
+        );
+        assert_eq!(types("a /= 2"), [IDENTIFIER, SLASH_EQUAL, NUMBER, EOF]);
+        assert_eq!(types("a // comment"), [IDENTIFIER, EOF]);
+        assert_eq!(types("a / ="), [IDENTIFIER, SLASH, EQUAL, EOF]);
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        use TokenType::*;
+        assert_eq!(types("i++"), [IDENTIFIER, PLUS_PLUS, EOF]);
+        assert_eq!(types("i--"), [IDENTIFIER, MINUS_MINUS, EOF]);
+        assert_eq!(types("a - -b"), [IDENTIFIER, MINUS, MINUS, IDENTIFIER, EOF]);
+        // with no space "--" is always the one token
+        assert_eq!(types("a--b"), [IDENTIFIER, MINUS_MINUS, IDENTIFIER, EOF]);
+        assert_eq!(types("--x"), [MINUS_MINUS, IDENTIFIER, EOF]);
+    }
+
+    #[test]
+    fn arrow() {
+        use TokenType::*;
+        assert_eq!(
+            lexemes("1->x"),
+            [
+                lexeme(NUMBER, "1"),
+                lexeme(ARROW, "->"),
+                lexeme(IDENTIFIER, "x")
+            ]
+        );
+        assert_eq!(
+            types("(a) -> a"),
+            [LEFT_PAREN, IDENTIFIER, RIGHT_PAREN, ARROW, IDENTIFIER, EOF]
+        );
+        assert_eq!(
+            types("a - > b"),
+            [IDENTIFIER, MINUS, GREATER, IDENTIFIER, EOF]
+        );
+        assert_eq!(types("=>"), [EQUAL, GREATER, EOF]);
+        assert_eq!(types("-->"), [MINUS_MINUS, GREATER, EOF]);
+    }
+
+    #[test]
+    fn every_keyword_is_in_the_table() {
+        use TokenType::*;
+        let keywords = [
+            ("and", AND),
+            ("break", BREAK),
+            ("class", CLASS),
+            ("const", CONST),
+            ("continue", CONTINUE),
+            ("else", ELSE),
+            ("false", FALSE),
+            ("for", FOR),
+            ("fun", FUN),
+            ("if", IF),
+            ("in", IN),
+            ("nil", NIL),
+            ("or", OR),
+            ("print", PRINT),
+            ("return", RETURN),
+            ("super", SUPER),
+            ("this", THIS),
+            ("true", TRUE),
+            ("var", VAR),
+            ("while", WHILE),
+        ];
+        for (keyword, token_type) in keywords {
+            assert_eq!(
+                TokenType::to_keyword(keyword),
+                Some(token_type),
+                "{keyword}"
+            );
+        }
+        assert_eq!(TokenType::keywords().len(), keywords.len());
+        assert_eq!(
+            types("while (x) { break; continue; }"),
+            [
+                WHILE,
+                LEFT_PAREN,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                LEFT_BRACE,
+                BREAK,
+                SEMICOLON,
+                CONTINUE,
+                SEMICOLON,
+                RIGHT_BRACE,
+                EOF
+            ]
+        );
+        for name in ["Break", "breaks", "continue_", "_in"] {
+            assert_eq!(TokenType::to_keyword(name), None, "{name}");
+        }
+    }
+
+    fn strings_with(source: &str, options: ScannerOptions) -> Vec<(String, String)> {
+        Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token.literal {
+                Some(Literal::Str(s)) => Some((token.lexeme.into_owned(), s.into_owned())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn raw_strings() {
+        let raw = || ScannerOptions::default().raw_strings(true);
+        assert_eq!(
+            strings_with(r#"r"C:\temp\new" r"a\nb""#, raw()),
+            [
+                (
+                    String::from(r#"r"C:\temp\new""#),
+                    String::from(r"C:\temp\new")
+                ),
+                (String::from(r#"r"a\nb""#), String::from(r"a\nb")),
+            ]
+        );
+        assert_eq!(
+            errors_with("r\"open\n", raw()),
+            [(1, String::from("Unterminated string."))]
+        );
+        let tokens = Scanner::with_options("r + r", raw()).scan_tokens().unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::IDENTIFIER);
+        assert_eq!(tokens[2].token_type, TokenType::IDENTIFIER);
+    }
+
+    #[test]
+    fn single_quoted_strings() {
+        let single = || ScannerOptions::default().single_quotes(true);
+        assert_eq!(
+            strings_with(r#"'say "hi"' "it's" '' 'a\tb'"#, single()),
+            [
+                (String::from(r#"'say "hi"'"#), String::from(r#"say "hi""#)),
+                (String::from(r#""it's""#), String::from("it's")),
+                (String::from("''"), String::new()),
+                (String::from(r"'a\tb'"), String::from("a\tb")),
+            ]
+        );
+        assert_eq!(
+            strings_with("'same'", single()).remove(0).1,
+            strings_with("\"same\"", single()).remove(0).1
+        );
+        assert_eq!(
+            errors_with("print 'open;\n", single()),
+            [(1, String::from("Unterminated string."))]
+        );
+    }
+
+    #[test]
+    fn keywords_round_trip() {
+        for &(keyword, token_type) in TokenType::keywords() {
+            assert_eq!(
+                TokenType::to_keyword(keyword),
+                Some(token_type),
+                "{keyword}"
+            );
+            assert_eq!(token_type.keyword_str(), Some(keyword), "{keyword}");
+        }
+    }
+
+    #[test]
+    fn streamed_tokens_match_in_memory_tokens() {
+        let snippet = "var s = \"héllo ${n + 1} 日本\"; // comment\n\
+                       /* block\n   comment */ fun f(a) { return a >= 0x1F; }\n\
+                       print \"two\nlines\" + 1_000.5e-3; @@\n";
+        let source = snippet.repeat(2 * 1024 * 1024 / snippet.len());
+        // errors included, in order
+        let describe = |result: Result<Token, LoxError>| format!("{result:?}");
+        let in_memory: Vec<_> = Scanner::with_options(&source, hex())
+            .map(describe)
+            .collect();
+        // a buffer this small splits tokens and characters between reads
+        let reader = io::BufReader::with_capacity(7, io::Cursor::new(source.as_bytes()));
+        let streamed: Vec<_> = Scanner::from_reader(reader, hex()).map(describe).collect();
+        assert_eq!(streamed.len(), in_memory.len());
+        // rather than assert_eq!, which would print megabytes of tokens
+        assert!(streamed == in_memory);
+    }
+
+    #[test]
+    fn string_lexemes_keep_their_quotes() {
+        let tokens = scan(r#""hi" "" "a\tb""#);
+        let strings: Vec<_> = tokens[..3]
+            .iter()
+            .map(|token| (token.lexeme.as_ref(), token.literal.clone()))
+            .collect();
+        assert_eq!(
+            strings,
+            [
+                ("\"hi\"", Some(Literal::Str("hi".into()))),
+                ("\"\"", Some(Literal::Str("".into()))),
+                (r#""a\tb""#, Some(Literal::Str("a\tb".into()))),
+            ]
+        );
+    }
+
+    fn errors_within(source: &str, limits: ScannerLimits) -> Vec<(usize, String)> {
+        match Scanner::with_limits(source, ScannerOptions::default(), limits).scan_tokens() {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| match error {
+                    LoxError::ParseError { line, message, .. } => (line, message),
+                    error => panic!("unexpected {error:?}"),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let program = "var a = \"ab\";\nprint a + 12345;\n";
+        let limits = |limits: ScannerLimits| errors_within(program, limits);
+        assert_eq!(
+            limits(ScannerLimits {
+                max_source_bytes: Some(20),
+                ..Default::default()
+            }),
+            [(
+                2,
+                String::from("Source is longer than the limit of 20 bytes.")
+            )]
+        );
+        assert_eq!(
+            limits(ScannerLimits {
+                max_tokens: Some(7),
+                ..Default::default()
+            }),
+            [(2, String::from("More tokens than the limit of 7."))]
+        );
+        assert_eq!(
+            limits(ScannerLimits {
+                max_string_length: Some(3),
+                ..Default::default()
+            }),
+            [(
+                1,
+                String::from("String literal is longer than the limit of 3 bytes.")
+            )]
+        );
+        assert_eq!(
+            limits(ScannerLimits {
+                max_number_length: Some(4),
+                ..Default::default()
+            }),
+            [(
+                2,
+                String::from("Number literal is longer than the limit of 4 bytes.")
+            )]
+        );
+        // and a program within them all is unaffected
+        let roomy = ScannerLimits {
+            max_source_bytes: Some(program.len()),
+            max_tokens: Some(11),
+            max_string_length: Some(4),
+            max_number_length: Some(5),
+        };
+        assert_eq!(limits(roomy), []);
+        // with both quotes and the prefix, r"ab" is five bytes
+        let raw = ScannerOptions::default().raw_strings(true);
+        let four = ScannerLimits {
+            max_string_length: Some(4),
+            ..Default::default()
+        };
+        assert!(Scanner::with_limits(r#"r"ab""#, raw, four)
+            .scan_tokens()
+            .is_err());
+    }
+
+    #[test]
+    fn keyword_introspection() {
+        use TokenType::*;
+        for &(keyword, token_type) in TokenType::keywords() {
+            assert!(token_type.is_keyword(), "{keyword}");
+        }
+        for token_type in [IDENTIFIER, NUMBER, STRING, EOF, PLUS] {
+            assert_eq!(token_type.keyword_str(), None);
+            assert!(!token_type.is_keyword());
+        }
+        for name in ["", "x", "iff", "returns", "CLASS"] {
+            assert_eq!(TokenType::to_keyword(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn token_lines() {
+        // every token's line, EOF last on the line where the last token ends
+        let cases: &[(&str, &[usize])] = &[
+            ("", &[1]),
+            ("\n\n", &[1]),
+            ("a", &[1, 1]),
+            ("a\n", &[1, 1]),
+            ("a\n\n\n", &[1, 1]),
+            ("\n\na", &[3, 3]),
+            ("a\r\nb\rc\n", &[1, 2, 3, 3]),
+            ("\"one\ntwo\" b", &[1, 2, 2]),
+            ("\"one\ntwo\"\n", &[1, 2]),
+            ("/* one\ntwo */ b", &[2, 2]),
+            ("a /* one\ntwo */\n", &[1, 2]),
+            ("a // comment\n", &[1, 1]),
+            ("a\n// comment\n\n", &[1, 2]),
+            ("\"${\nx\n}\" y", &[1, 2, 3, 3, 3]),
+        ];
+        for (source, lines) in cases {
+            let scanned: Vec<_> = Scanner::with_options(source, interpolating())
+                .map(|token| token.unwrap().line)
+                .collect();
+            assert_eq!(scanned, *lines, "{source:?}");
+        }
+        assert_eq!(
+            errors("\n\n@"),
+            [(3, String::from("Unexpected character '@' (U+0040)."))]
+        );
+    }
+
+    #[test]
+    fn literals_differ_from_lexemes() {
+        let tokens = scan(r#"1.50 "a\"b\u{e9}""#);
+        assert_eq!(tokens[0].lexeme, "1.50");
+        assert_eq!(tokens[0].literal, Some(Literal::Number(1.5)));
+        assert_eq!(tokens[1].lexeme, r#""a\"b\u{e9}""#);
+        assert_eq!(tokens[1].literal, Some(Literal::Str("a\"bé".into())));
+        // tokens with equal lexemes but different literals aren't equal
+        let mut changed = tokens[0].clone();
+        changed.literal = Some(Literal::Number(1.0));
+        assert_ne!(changed, tokens[0]);
+        assert!(format!("{:?}", tokens[0]).contains("Number(1.5)"));
+    }
+
+    fn warnings(source: &str) -> Vec<(usize, String)> {
+        warnings_with(source, ScannerOptions::default())
+    }
+
+    fn warnings_with(source: &str, options: ScannerOptions) -> Vec<(usize, String)> {
+        let tokens = Scanner::with_options(source, options.clone())
+            .scan_tokens()
+            .unwrap();
+        keyword_case_warnings(&tokens, &options)
+            .into_iter()
+            .map(|warning| match warning {
+                LoxError::Warning { line, message, .. } => (line, message),
+                warning => panic!("unexpected {warning:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keyword_case() {
+        assert_eq!(
+            warnings("If (x) print 1;\nvar a = TRUE;\nvar classify = Nil_;"),
+            [
+                (
+                    1,
+                    String::from("'If' is an identifier, not a keyword; did you mean `if`?")
+                ),
+                (
+                    2,
+                    String::from("'TRUE' is an identifier, not a keyword; did you mean `true`?")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn extensions_are_opt_in() {
+        use TokenType::*;
+        let source = "const é = 0xFF + 'a' + r\"b\\c\";\nfor (x in xs) {}";
+        assert_eq!(
+            errors(source),
+            [
+                (1, String::from("Unexpected character 'é' (U+00E9).")),
+                (1, String::from(r#"Unexpected characters "\'\'"."#)),
+                (1, String::from(r"Invalid escape sequence '\c'.")),
+            ]
+        );
+        let everything = ScannerOptions::default()
+            .hex_literals(true)
+            .single_quotes(true)
+            .raw_strings(true)
+            .unicode_identifiers(true)
+            .in_keyword(true)
+            .const_keyword(true);
+        let tokens = Scanner::with_options(source, everything)
+            .scan_tokens()
+            .unwrap();
+        let scanned: Vec<_> = tokens.iter().map(|token| token.token_type).collect();
+        assert_eq!(
+            scanned,
+            [
+                CONST,
+                IDENTIFIER,
+                EQUAL,
+                NUMBER,
+                PLUS,
+                STRING,
+                PLUS,
+                STRING,
+                SEMICOLON,
+                FOR,
+                LEFT_PAREN,
+                IDENTIFIER,
+                IN,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                LEFT_BRACE,
+                RIGHT_BRACE,
+                EOF
+            ]
+        );
+        // without them `in` is a name, and 0xFF is 0 then a name; only const
+        // is a keyword by default
+        assert_eq!(
+            types("const k = 0xFF in;"),
+            [CONST, IDENTIFIER, EQUAL, NUMBER, IDENTIFIER, IDENTIFIER, SEMICOLON, EOF]
+        );
+    }
+
+    #[test]
+    fn scan_stats() {
+        use TokenType::*;
+        let source = "// a comment\nvar greeting = \"hello\";\nprint greeting; /* another */\n";
+        let (result, stats) = Scanner::new(source).scan_tokens_with_stats();
+        assert!(result.is_ok());
+        let by_type = HashMap::from([
+            (VAR, 1),
+            (IDENTIFIER, 2),
+            (EQUAL, 1),
+            (STRING, 1),
+            (SEMICOLON, 2),
+            (PRINT, 1),
+            (EOF, 1),
+        ]);
+        assert_eq!(
+            stats,
+            ScanStats {
+                tokens: 9,
+                tokens_by_type: by_type,
+                lines: 3,
+                comment_chars: 12 + 13,
+                longest_token: 8,
+            }
+        );
+        let (_, empty) = Scanner::new("").scan_tokens_with_stats();
+        assert_eq!((empty.tokens, empty.lines), (1, 0));
+    }
+
+    #[test]
+    fn line_endings() {
+        let program = "var a = 1;\n// comment\nprint \"two\nlines\";\n\nprint a;\n";
+        let scanned = |ending: &str| -> Vec<_> {
+            scan(&program.replace('\n', ending))
+                .into_iter()
+                .map(|token| {
+                    let literal = token.literal.map(Literal::into_owned);
+                    (token.token_type, token.line, token.column, literal)
+                })
+                .collect()
+        };
+        let lf = scanned("\n");
+        assert_eq!(lf.last().map(|token| token.1), Some(6));
+        assert_eq!(scanned("\r\n"), lf);
+        assert_eq!(scanned("\r"), lf);
+    }
+
+    #[test]
+    fn unusual_whitespace() {
+        use TokenType::*;
+        assert_eq!(
+            types("a\x0Bb\x0Cc"),
+            [IDENTIFIER, IDENTIFIER, IDENTIFIER, EOF]
+        );
+        let reported = Scanner::new("var a =\u{a0}1;").scan_tokens().unwrap_err();
+        assert!(matches!(
+            &reported[..],
+            [LoxError::ParseError { line: 1, column: 8, message }]
+                if message == "Non-breaking space found; replace with a regular space."
+        ));
+        assert_eq!(string_value("\"a\u{a0}b\""), "a\u{a0}b");
+    }
+
+    #[test]
+    fn in_keyword() {
+        use TokenType::*;
+        let source = "for (x in xs)";
+        let options = ScannerOptions::default().in_keyword(true);
+        let tokens = Scanner::with_options(source, options.clone())
+            .scan_tokens()
+            .unwrap();
+        let scanned: Vec<_> = tokens.iter().map(|token| token.token_type).collect();
+        assert_eq!(
+            scanned,
+            [
+                FOR,
+                LEFT_PAREN,
+                IDENTIFIER,
+                IN,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                EOF
+            ]
+        );
+        assert_eq!(
+            types(source),
+            [
+                FOR,
+                LEFT_PAREN,
+                IDENTIFIER,
+                IDENTIFIER,
+                IDENTIFIER,
+                RIGHT_PAREN,
+                EOF
+            ]
+        );
+        // `In` only looks like a misspelt keyword when `in` is one
+        assert_eq!(warnings("var In = in;"), []);
+        assert_eq!(
+            warnings_with("var In;", options),
+            [(
+                1,
+                String::from("'In' is an identifier, not a keyword; did you mean `in`?")
+            )]
+        );
+    }
+
+    #[test]
+    fn const_keyword() {
+        use TokenType::*;
+        let source = "const x = 1;";
+        assert_eq!(
+            types(source),
+            [CONST, IDENTIFIER, EQUAL, NUMBER, SEMICOLON, EOF]
+        );
+        let options = ScannerOptions::default().const_keyword(false);
+        let tokens = Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap();
+        let scanned: Vec<_> = tokens.iter().map(|token| token.token_type).collect();
+        assert_eq!(
+            scanned,
+            [IDENTIFIER, IDENTIFIER, EQUAL, NUMBER, SEMICOLON, EOF]
+        );
+        assert_eq!(TokenType::to_keyword("const"), Some(CONST));
+        assert_eq!(CONST.keyword_str(), Some("const"));
+    }
+
+    // the tokens scanned despite errors, and the errors
+    fn recovered(source: &str) -> (Vec<TokenType>, Vec<String>) {
+        let mut scanned = Vec::new();
+        let mut reported = Vec::new();
+        for result in Scanner::new(source) {
+            match result {
+                Ok(token) => scanned.push(token.token_type),
+                Err(LoxError::ParseError { message, .. }) => reported.push(message),
+                Err(error) => panic!("unexpected {error:?}"),
+            }
+        }
+        (scanned, reported)
+    }
+
+    #[test]
+    fn resynchronizing_after_unexpected_characters() {
+        use TokenType::*;
+        // "le", then nothing more of the word
+        let (scanned, reported) = recovered("le©t x = 1;");
+        assert_eq!(
+            scanned,
+            [IDENTIFIER, IDENTIFIER, EQUAL, NUMBER, SEMICOLON, EOF]
+        );
+        assert_eq!(reported, ["Unexpected character '©' (U+00A9)."]);
+        let (scanned, reported) = recovered("a =©= b;");
+        assert_eq!(
+            scanned,
+            [IDENTIFIER, EQUAL, EQUAL, IDENTIFIER, SEMICOLON, EOF]
+        );
+        assert_eq!(reported, ["Unexpected character '©' (U+00A9)."]);
+        let (scanned, reported) = recovered("pr§i¶nt \"ok\";");
+        assert_eq!(scanned, [IDENTIFIER, STRING, SEMICOLON, EOF]);
+        assert_eq!(reported, ["Unexpected characters \"§¶\"."]);
+    }
+
+    #[test]
+    fn doc_comments() {
+        use TokenType::*;
+        let source = "/// Adds.\n///   Indented.\n////Slashed.\nfun f() {} // plain\n";
+        let tokens: Vec<_> =
+            Scanner::with_options(source, ScannerOptions::default().doc_comments(true))
+                .scan_tokens()
+                .unwrap()
+                .into_iter()
+                .map(|token| (token.token_type, token.literal))
+                .collect();
+        let doc = |text| (DOC_COMMENT, Some(Literal::Str(Cow::Borrowed(text))));
+        assert_eq!(
+            tokens[..4],
+            [
+                doc("Adds."),
+                doc("  Indented."),
+                doc("/Slashed."),
+                (FUN, None)
+            ]
+        );
+        assert_eq!(tokens.len(), 10);
+        assert_eq!(types(source)[0], FUN);
+    }
+
+    #[test]
+    fn scan_with_a_callback() {
+        let source = "var a = 1; print a; var b = a + 2; print b;";
+        let mut count = 0;
+        let mut scanner = Scanner::new(source);
+        let result = scanner.scan_with(|_| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(count, scan(source).len());
+
+        // stopping at the tenth token leaves the rest to scan
+        let mut seen = Vec::new();
+        let mut scanner = Scanner::new(source);
+        let result = scanner.scan_with(|token| {
+            seen.push(token.token_type);
+            if seen.len() == 10 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(seen.len(), 10);
+        let rest = scanner.scan_all().unwrap();
+        assert_eq!(seen.len() + rest.len(), scan(source).len());
+
+        // errors are gathered while the tokens around them still arrive
+        let mut last = None;
+        let result = Scanner::new("a @ b\n` c").scan_with(|token| {
+            last = Some(token.token_type);
+            ControlFlow::Continue(())
+        });
+        let lines: Vec<_> = result
+            .unwrap_err()
+            .into_iter()
+            .map(|error| match error {
+                LoxError::ParseError { line, .. } => line,
+                error => panic!("unexpected {error:?}"),
+            })
+            .collect();
+        assert_eq!(lines, [1, 2]);
+        assert_eq!(last, Some(TokenType::EOF));
+    }
+
+    #[test]
+    fn numbers_too_large_for_an_f64() {
+        assert_eq!(
+            errors("print 1e400;"),
+            [(1, String::from("Invalid number literal '1e400'."))]
+        );
+        assert_eq!(number("1.7976931348623157e308"), f64::MAX);
+        // 2^1024 is past the largest f64, and 2^1020 isn't
+        let literal = |zeros| format!("0x1{}", "0".repeat(zeros));
+        let message = format!("Invalid hexadecimal literal '{}'.", literal(256));
+        assert_eq!(errors_with(&literal(256), hex()), [(1, message)]);
+        assert_eq!(number_with(&literal(255), hex()), 2f64.powi(1020));
+    }
+
+    #[test]
+    fn nothing_is_left_after_eof() {
+        use TokenType::*;
+        let mut scanner = Scanner::new("print 1;");
+        let tokens = scanner.scan_all().unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.token_type)
+                .collect::<Vec<_>>(),
+            [PRINT, NUMBER, SEMICOLON, EOF]
+        );
+        assert!(matches!(scanner.scan_all(), Ok(tokens) if tokens.is_empty()));
+        let mut calls = 0;
+        let result = scanner.scan_with(|_| {
+            calls += 1;
+            ControlFlow::Continue(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(calls, 0);
+        assert!(scanner.next_token().is_none());
+    }
+
+    #[test]
+    fn carets_line_up_after_tabs() {
+        let caret = |source, tab_width, line, column| {
+            SourceMap::with_tab_width(source, tab_width)
+                .caret(line, column)
+                .unwrap()
+        };
+        assert_eq!(caret("\tx", 4, 1, 2), "    x\n    ^");
+        assert_eq!(caret("\tx", 8, 1, 2), "        x\n        ^");
+        // a tab moves to the next stop, however far off it is
+        assert_eq!(caret("a\t\tb", 4, 1, 4), "a       b\n        ^");
+        assert_eq!(
+            caret("a\t\tb", 8, 1, 4),
+            "a               b\n                ^"
+        );
+        assert_eq!(
+            SourceMap::with_tab_width("a\t\tb", 4).display_column(1, 4),
+            Some(9)
+        );
+        assert_eq!(SourceMap::new("a\t\tb").display_column(1, 4), Some(17));
+        // past the end of the line, as at EOF
+        assert_eq!(caret("\tab", 4, 1, 4), "    ab\n      ^");
+        assert_eq!(caret("\tab", 4, 1, 6), "    ab\n        ^");
+        assert_eq!(caret("a;\r\n\tb\r\n", 4, 2, 2), "    b\n    ^");
+        assert_eq!(SourceMap::new("a").caret(2, 1), None);
+    }
+}
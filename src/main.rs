@@ -1,37 +1,184 @@
-use craftinginterpreters::{LoxError, Scanner, Token};
+use craftinginterpreters::{
+    keyword_case_warnings,
+    parser::Parser,
+    printer::{program_json, AstPrinter},
+    LoxError, ScanStats, Scanner, ScannerOptions, SourceMap, Token,
+};
 use std::{
-    env, fs,
-    io::{self, Write},
+    cmp::Reverse,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
     path::Path,
     process,
 };
 
+const USAGE: &str = "Usage: rlox [--strict-newlines] [--lang-ext=EXT,...] [--tab-width N] \
+                     [--warn-keyword-case] [--stats] [--ast] [--ast-json] [script | -]
+Extensions: hex, single-quotes, raw-strings, interpolation, unicode-idents, in, const \
+(default: const; listing any replaces the default)";
+
+// what gets reported and how, as opposed to how the source is scanned
+struct Report {
+    tab_width: usize,
+    keyword_case: bool,
+    stats: bool,
+    // print the syntax tree parenthesized rather than as Rust debug output
+    ast: bool,
+    // print the whole syntax tree as one JSON document
+    ast_json: bool,
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut options = ScannerOptions::default();
+    let mut report = Report {
+        tab_width: 8,
+        keyword_case: false,
+        stats: false,
+        ast: false,
+        ast_json: false,
+    };
+    let mut scripts = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strict-newlines" => options.strict_newlines = true,
+            "--warn-keyword-case" => report.keyword_case = true,
+            "--stats" => report.stats = true,
+            "--ast" => report.ast = true,
+            "--ast-json" => report.ast_json = true,
+            "--tab-width" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => report.tab_width = n,
+                _ => {
+                    print!("{USAGE}");
+                    return;
+                }
+            },
+            _ if arg.starts_with("--lang-ext=") => {
+                // only the extensions listed are enabled; the rest of the
+                // options are kept as the flags before this one set them
+                let mut enabled = ScannerOptions {
+                    strict_newlines: options.strict_newlines,
+                    // on by default, but here only if listed
+                    const_keyword: false,
+                    ..Default::default()
+                };
+                for ext in arg["--lang-ext=".len()..].split(',') {
+                    enabled = match ext {
+                        "hex" => enabled.hex_literals(true),
+                        "single-quotes" => enabled.single_quotes(true),
+                        "raw-strings" => enabled.raw_strings(true),
+                        "interpolation" => enabled.interpolation(true),
+                        "unicode-idents" => enabled.unicode_identifiers(true),
+                        "in" => enabled.in_keyword(true),
+                        "const" => enabled.const_keyword(true),
+                        "" => enabled,
+                        _ => {
+                            print!("{USAGE}");
+                            return;
+                        }
+                    };
+                }
+                options = enabled;
+            }
+            _ if arg.starts_with("--") => {
+                print!("{USAGE}");
+                return;
+            }
+            _ => scripts.push(arg),
+        }
+    }
 
-    match args.len() {
-        1 => run_prompt(),
-        2 => run_file(&args[1]),
-        _ => print!("Usage: rlox [script]"),
+    match scripts.as_slice() {
+        [] => run_prompt(&options, &report),
+        [script] if script == "-" => {
+            let errors = run_reader(io::stdin().lock(), &options, &report);
+            // what was read from stdin is gone, so there is nothing to quote
+            exit_on_errors(errors, None, &report);
+        }
+        [script] => run_file(script, &options, &report),
+        _ => print!("{USAGE}"),
     }
 }
 
-fn run_file(path: impl AsRef<Path>) {
+fn run_file(path: impl AsRef<Path>, options: &ScannerOptions, report: &Report) {
     let path_string = path.as_ref().to_string_lossy().to_string();
-    let code = match fs::read_to_string(path) {
-        Ok(code) => code,
+    let file = match File::open(&path) {
+        Ok(file) => file,
         Err(error) => {
             eprintln!("Unable to read file {}: {}", path_string, error);
             process::exit(74);
         }
     };
-    if let Err(error) = run(code) {
-        error_report(error);
-        std::process::exit(65);
+    let errors = run_reader(BufReader::new(file), options, report);
+    // the script is read again only to quote it in error messages
+    let source = if errors.is_empty() {
+        None
+    } else {
+        fs::read_to_string(&path).ok()
+    };
+    exit_on_errors(errors, source.as_deref(), report);
+}
+
+// Scripts are scanned as they are read rather than loaded whole.
+fn run_reader(reader: impl BufRead, options: &ScannerOptions, report: &Report) -> Vec<LoxError> {
+    let scanner = Scanner::from_reader(reader, options.clone());
+    let tokens = if report.stats {
+        let (tokens, stats) = scanner.scan_tokens_with_stats();
+        stats_report(&stats);
+        tokens
+    } else {
+        scanner.scan_tokens()
+    };
+    check_and_run(tokens, options, report)
+}
+
+// Runs the tokens unless scanning failed, returning errors and warnings.
+fn check_and_run(
+    tokens: Result<Vec<Token>, Vec<LoxError>>,
+    options: &ScannerOptions,
+    report: &Report,
+) -> Vec<LoxError> {
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        Err(errors) => return errors,
+    };
+    let mut errors = if report.keyword_case {
+        keyword_case_warnings(&tokens, options)
+    } else {
+        Vec::new()
+    };
+    if let Err(run_errors) = run(tokens, report) {
+        errors.extend(run_errors);
     }
+    errors
 }
 
-fn run_prompt() {
+// Prints every error and warning, exiting unless there were only warnings.
+fn exit_on_errors(errors: Vec<LoxError>, source: Option<&str>, report: &Report) {
+    if errors.is_empty() {
+        return;
+    }
+    let read_failed = errors
+        .iter()
+        .any(|error| matches!(error, LoxError::IoError { .. }));
+    let failed = errors
+        .iter()
+        .any(|error| !matches!(error, LoxError::Warning { .. }));
+    print_errors(errors, source, report);
+    if failed {
+        process::exit(if read_failed { 74 } else { 65 });
+    }
+}
+
+fn print_errors(errors: Vec<LoxError>, source: Option<&str>, report: &Report) {
+    let source_map = source.map(|source| SourceMap::with_tab_width(source, report.tab_width));
+    for error in errors {
+        error_report(error, source_map.as_ref());
+    }
+}
+
+fn run_prompt(options: &ScannerOptions, report: &Report) {
     loop {
         print!("> ");
         io::stdout().flush().expect("Unable to flush stdout");
@@ -42,29 +189,65 @@ fn run_prompt() {
         if n == 0 {
             break;
         }
-        if let Err(error) = run(line) {
-            error_report(error);
-            std::process::exit(65);
+        let scanner = Scanner::with_options(&line, options.clone());
+        let tokens = if report.stats {
+            let (tokens, stats) = scanner.scan_tokens_with_stats();
+            stats_report(&stats);
+            tokens
+        } else {
+            scanner.scan_tokens()
         };
+        let errors = check_and_run(tokens, options, report);
+        // a mistake at the prompt only costs the line it was made on
+        print_errors(errors, Some(&line), report);
     }
 }
 
-fn run(source: String) -> Result<(), LoxError> {
-    let scanner = Scanner::new(&source);
-    let tokens: Vec<Token> = scanner.scan_tokens()?;
-
-    // for now, just print the tokens
-    for token in tokens {
-        println!("{token:?}");
+fn run(tokens: Vec<Token>, report: &Report) -> Result<(), Vec<LoxError>> {
+    // for now, just print the statements
+    let (statements, errors) = Parser::new(tokens).parse();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    if report.ast_json {
+        println!("{}", program_json(&statements));
+        return Ok(());
+    }
+    for statement in statements {
+        if report.ast {
+            println!("{}", AstPrinter.print_stmt(&statement));
+        } else {
+            println!("{statement:?}");
+        }
     }
     Ok(())
 }
 
+// on stderr, to keep stdout to the program's own output
+fn stats_report(stats: &ScanStats) {
+    eprintln!("tokens: {}", stats.tokens);
+    eprintln!("lines: {}", stats.lines);
+    eprintln!("comment characters: {}", stats.comment_chars);
+    eprintln!("longest token: {}", stats.longest_token);
+    let mut by_type: Vec<_> = stats.tokens_by_type.iter().collect();
+    // most common first, then by name for a stable order
+    by_type.sort_by_key(|(token_type, count)| (Reverse(**count), token_type.to_string()));
+    for (token_type, count) in by_type {
+        eprintln!("  {token_type}: {count}");
+    }
+}
+
 //TODO: add error type name into error message
-fn error_report(error: LoxError) {
-    match error {
-        LoxError::ParseError { line, message } => {
-            eprintln!("[line {line}] Error: {message}")
+fn error_report(error: LoxError, source_map: Option<&SourceMap>) {
+    eprintln!("{error}");
+    let position = match &error {
+        LoxError::ParseError { line, column, .. } | LoxError::Warning { line, column, .. } => {
+            Some((*line, *column))
         }
+        LoxError::SyntaxError { token, .. } => Some((token.line, token.column)),
+        LoxError::IoError { .. } => None,
+    };
+    if let Some(caret) = position.and_then(|(line, column)| source_map?.caret(line, column)) {
+        eprintln!("{caret}");
     }
 }
@@ -0,0 +1,1566 @@
+use crate::{
+    ast::{Expr, ExprKind, Function, LiteralValue, LoopSyntax, Spanned, Stmt, StmtKind},
+    Literal, LoxError, Token, TokenType,
+};
+use std::{borrow::Cow, mem};
+
+// A recursive descent parser over the scanner's tokens, one method per rule
+// of the grammar, from the loosest binding to the tightest:
+//
+//   program      → declaration* EOF ;
+//   declaration  → classDecl | funDecl | varDecl | statement ;
+//   classDecl    → "class" IDENTIFIER ( "<" IDENTIFIER )?
+//                  "{" ( "class"? ( function | getter ) )* "}" ;
+//   getter       → IDENTIFIER block ;
+//   funDecl      → "fun" function ;
+//   function     → IDENTIFIER "(" parameters? ")" block ;
+//   parameters   → IDENTIFIER ( "," IDENTIFIER )* ;
+//   varDecl      → "var" IDENTIFIER ( "=" expression )? ";" ;
+//   statement    → exprStmt | breakStmt | continueStmt | forStmt | ifStmt
+//                | printStmt | returnStmt | whileStmt | block ;
+//   breakStmt    → "break" ";" ;
+//   continueStmt → "continue" ";" ;
+//   forStmt      → "for" "(" ( varDecl | exprStmt | ";" )
+//                  expression? ";" expression? ")" statement ;
+//   ifStmt       → "if" "(" expression ")" statement ( "else" statement )? ;
+//   returnStmt   → "return" expression? ";" ;
+//   whileStmt    → "while" "(" expression ")" statement ;
+//   block        → "{" declaration* "}" ;
+//   exprStmt     → expression ";" ;
+//   printStmt    → "print" expression ";" ;
+//   expression   → comma ;
+//   comma        → assignment ( "," assignment )* ;
+//   assignment   → ( call "." )? IDENTIFIER "=" assignment
+//                | call "[" expression "]" "=" assignment | conditional ;
+//   conditional  → logic_or ( "?" expression ":" conditional )? ;
+//   logic_or     → logic_and ( "or" logic_and )* ;
+//   logic_and    → equality ( "and" equality )* ;
+//   equality     → comparison ( ( "!=" | "==" ) comparison )* ;
+//   comparison   → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+//   term         → factor ( ( "-" | "+" ) factor )* ;
+//   factor       → unary ( ( "/" | "*" | "%" ) unary )* ;
+//   unary        → ( "!" | "-" ) unary | call ;
+//   call         → primary ( "(" arguments? ")" | "." IDENTIFIER
+//                  | "[" expression "]" )* ;
+//   arguments    → assignment ( "," assignment )* ;
+//   primary      → NUMBER | STRING | "true" | "false" | "nil"
+//                | "(" expression ")" | IDENTIFIER | "this" | lambda
+//                | "super" "." IDENTIFIER | array | map | interpolation ;
+//   interpolation → INTERPOLATION expression
+//                  ( INTERPOLATION expression )* STRING ;
+//   array        → "[" ( assignment ( "," assignment )* ","? )? "]" ;
+//   map          → "{" ( entry ( "," entry )* ","? )? "}" ;
+//   entry        → assignment ":" assignment ;
+//   lambda       → "fun" "(" parameters? ")" block ;
+//
+// where primary also takes a binary operator with only its right operand,
+// to report that the left one is missing.
+
+// as many as a call can pass, or a function take, as in clox, which has a
+// byte for the count
+const MAX_ARGUMENTS: usize = 255;
+
+#[derive(Clone, Copy)]
+enum FunctionKind {
+    Function,
+    Lambda,
+    Method,
+    StaticMethod,
+}
+
+impl FunctionKind {
+    // as it is called in error messages
+    fn name(self) -> &'static str {
+        match self {
+            FunctionKind::Function | FunctionKind::Lambda => "function",
+            FunctionKind::Method | FunctionKind::StaticMethod => "method",
+        }
+    }
+}
+
+pub struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    current: usize,
+    errors: Vec<LoxError>,
+    // how many loops the statement being parsed is in
+    loop_depth: usize,
+    // and how many classes
+    class_depth: usize,
+    // whether that is in a static method, where there is no `this`
+    in_static_method: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token<'a>>) -> Self {
+        // comments matter to tools working on tokens, not to the grammar
+        let mut tokens: Vec<_> = tokens
+            .into_iter()
+            .filter(|token| {
+                !matches!(
+                    token.token_type,
+                    TokenType::COMMENT | TokenType::DOC_COMMENT
+                )
+            })
+            .collect();
+        // the rules rely on the last token being EOF to stop at
+        if tokens.last().map(|token| token.token_type) != Some(TokenType::EOF) {
+            let (line, column, end) = tokens.last().map_or((1, 1, 0), |token| {
+                (token.line, token.column, token.span.end)
+            });
+            tokens.push(Token {
+                token_type: TokenType::EOF,
+                lexeme: Cow::Borrowed(""),
+                literal: None,
+                line,
+                column,
+                span: end..end,
+            });
+        }
+        Parser {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            loop_depth: 0,
+            class_depth: 0,
+            in_static_method: false,
+        }
+    }
+
+    // Parses the whole program. A syntax error doesn't stop parsing: the
+    // parser skips ahead to where the next statement is likely to start and
+    // carries on, so one run reports every error it can. What did parse is
+    // returned either way, for tools that want as much of the tree as there is.
+    pub fn parse(&mut self) -> (Vec<Stmt<'a>>, Vec<LoxError>) {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            statements.extend(self.declaration());
+        }
+        (statements, mem::take(&mut self.errors))
+    }
+
+    // Where errors are caught: a declaration that doesn't parse is recorded
+    // and skipped, and parsing resumes with the next one.
+    fn declaration(&mut self) -> Option<Stmt<'a>> {
+        let start = self.peek().span.start;
+        // without a name, `fun` starts a lambda in an expression statement
+        let result = if self.check(TokenType::FUN) && self.check_next(TokenType::IDENTIFIER) {
+            self.advance();
+            self.function(FunctionKind::Function)
+                .map(StmtKind::Function)
+        } else if self.match_any(&[TokenType::CLASS]) {
+            self.class_declaration()
+        } else if self.match_any(&[TokenType::VAR]) {
+            self.var_declaration()
+        } else {
+            self.statement_kind()
+        };
+        match result {
+            Ok(kind) => Some(self.spanned(start, kind)),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let name = self
+            .consume(TokenType::IDENTIFIER, "Expect class name.")?
+            .clone();
+        let superclass = if self.match_any(&[TokenType::LESS]) {
+            let name = self.consume(TokenType::IDENTIFIER, "Expect superclass name.")?;
+            Some(Expr::variable(name.clone()))
+        } else {
+            None
+        };
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.")?;
+        let mut methods = Vec::new();
+        self.class_depth += 1;
+        let in_static_method = mem::take(&mut self.in_static_method);
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let kind = if self.match_any(&[TokenType::CLASS]) {
+                FunctionKind::StaticMethod
+            } else {
+                FunctionKind::Method
+            };
+            match self.function(kind) {
+                Ok(method) => methods.push(method),
+                // one bad method needn't cost the rest of the class
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize_method();
+                }
+            }
+        }
+        self.class_depth -= 1;
+        self.in_static_method = in_static_method;
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.")?;
+        Ok(StmtKind::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn function(&mut self, kind: FunctionKind) -> Result<Function<'a>, LoxError> {
+        let message = format!("Expect {} name.", kind.name());
+        let name = self.consume(TokenType::IDENTIFIER, &message)?.clone();
+        let is_method = matches!(kind, FunctionKind::Method | FunctionKind::StaticMethod);
+        // only a method can leave out its parameter list, to be a getter
+        let is_getter = is_method && self.check(TokenType::LEFT_BRACE);
+        if !is_getter {
+            let message = format!("Expect '(' after {} name.", kind.name());
+            self.consume(TokenType::LEFT_PAREN, &message)?;
+        }
+        let is_static = matches!(kind, FunctionKind::StaticMethod);
+        // a function declared in a static method has no `this` either
+        let in_static_method = match kind {
+            FunctionKind::Method | FunctionKind::StaticMethod => is_static,
+            FunctionKind::Function | FunctionKind::Lambda => self.in_static_method,
+        };
+        let in_static_method = mem::replace(&mut self.in_static_method, in_static_method);
+        let result = if is_getter {
+            self.advance();
+            self.function_block().map(|body| (Vec::new(), body))
+        } else {
+            self.function_body(kind)
+        };
+        self.in_static_method = in_static_method;
+        let (params, body) = result?;
+        Ok(Function {
+            name,
+            params,
+            body,
+            is_static,
+            is_getter,
+        })
+    }
+
+    // The parameters and body of a function, named or not, from just after
+    // the opening parenthesis.
+    fn function_body(
+        &mut self,
+        kind: FunctionKind,
+    ) -> Result<(Vec<Token<'a>>, Vec<Stmt<'a>>), LoxError> {
+        let mut params = Vec::new();
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                if params.len() == MAX_ARGUMENTS {
+                    let message = format!("Can't have more than {MAX_ARGUMENTS} parameters.");
+                    self.errors.push(error(self.peek(), None, &message));
+                }
+                params.push(
+                    self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?
+                        .clone(),
+                );
+                if !self.match_any(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.")?;
+        let message = format!("Expect '{{' before {} body.", kind.name());
+        self.consume(TokenType::LEFT_BRACE, &message)?;
+        Ok((params, self.function_block()?))
+    }
+
+    // from just after the opening brace
+    fn function_block(&mut self) -> Result<Vec<Stmt<'a>>, LoxError> {
+        // loops around the declaration are no place to break out of
+        let loop_depth = mem::take(&mut self.loop_depth);
+        let body = self.block();
+        self.loop_depth = loop_depth;
+        body
+    }
+
+    fn var_declaration(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let name = self
+            .consume(TokenType::IDENTIFIER, "Expect variable name.")?
+            .clone();
+        let initializer = if self.match_any(&[TokenType::EQUAL]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::SEMICOLON,
+            "Expect ';' after variable declaration.",
+        )?;
+        Ok(StmtKind::Var { name, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Stmt<'a>, LoxError> {
+        let start = self.peek().span.start;
+        let kind = self.statement_kind()?;
+        Ok(self.spanned(start, kind))
+    }
+
+    fn statement_kind(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        if self.match_any(&[TokenType::BREAK, TokenType::CONTINUE]) {
+            return self.jump_statement();
+        }
+        if self.match_any(&[TokenType::FOR]) {
+            return self.for_statement();
+        }
+        if self.match_any(&[TokenType::IF]) {
+            return self.if_statement();
+        }
+        if self.match_any(&[TokenType::PRINT]) {
+            return self.print_statement();
+        }
+        if self.match_any(&[TokenType::RETURN]) {
+            return self.return_statement();
+        }
+        if self.match_any(&[TokenType::WHILE]) {
+            return self.while_statement();
+        }
+        if self.match_any(&[TokenType::LEFT_BRACE]) {
+            return Ok(StmtKind::Block(self.block()?));
+        }
+        self.expression_statement()
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt<'a>>, LoxError> {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            statements.extend(self.declaration());
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    // break and continue, which differ only in where they jump to
+    fn jump_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let keyword = self.previous().clone();
+        let message = format!("Expect ';' after '{}'.", keyword.lexeme);
+        self.consume(TokenType::SEMICOLON, &message)?;
+        if self.loop_depth == 0 {
+            // a jump in the wrong place doesn't confuse the parser
+            let message = format!("Must be inside a loop to use '{}'.", keyword.lexeme);
+            self.errors.push(error(&keyword, None, &message));
+        }
+        Ok(match keyword.token_type {
+            TokenType::BREAK => StmtKind::Break(keyword),
+            _ => StmtKind::Continue(keyword),
+        })
+    }
+
+    // There is no for loop in the tree: it comes out as the while loop it
+    // amounts to, in a block with the initializer. The increment is kept
+    // apart from the body, so that a continue doesn't skip it.
+    fn for_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let start = self.previous().span.start;
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.")?;
+        let initializer = if self.match_any(&[TokenType::SEMICOLON]) {
+            None
+        } else {
+            let initializer_start = self.peek().span.start;
+            let initializer = if self.match_any(&[TokenType::VAR]) {
+                self.var_declaration()?
+            } else {
+                self.expression_statement()?
+            };
+            Some(self.spanned(initializer_start, initializer))
+        };
+        let condition = if self.check(TokenType::SEMICOLON) {
+            // an empty span where the condition was left out
+            let at = self.peek().span.start;
+            Spanned::new(ExprKind::Literal(LiteralValue::Bool(true)), at..at)
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")?;
+        let increment = if self.check(TokenType::RIGHT_PAREN) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.")?;
+
+        let body = Box::new(self.loop_body()?);
+        let while_loop = StmtKind::While {
+            condition,
+            body,
+            increment,
+            syntax: LoopSyntax::For {
+                initializer: initializer.is_some(),
+            },
+        };
+        Ok(match initializer {
+            Some(initializer) => {
+                StmtKind::Block(vec![initializer, self.spanned(start, while_loop)])
+            }
+            None => while_loop,
+        })
+    }
+
+    // An `else` goes with the nearest `if` before it, since the innermost
+    // if statement is the first to look for one.
+    fn if_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.")?;
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_any(&[TokenType::ELSE]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn print_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
+        Ok(StmtKind::Print(value))
+    }
+
+    // Whether there is anything to return from is for a later pass to say.
+    fn return_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let keyword = self.previous().clone();
+        let value = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        Ok(StmtKind::Return { keyword, value })
+    }
+
+    fn while_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.")?;
+        let body = Box::new(self.loop_body()?);
+        Ok(StmtKind::While {
+            condition,
+            body,
+            increment: None,
+            syntax: LoopSyntax::While,
+        })
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt<'a>, LoxError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn expression_statement(&mut self) -> Result<StmtKind<'a>, LoxError> {
+        let expression = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.")?;
+        Ok(StmtKind::Expression(expression))
+    }
+
+    fn expression(&mut self) -> Result<Expr<'a>, LoxError> {
+        self.comma()
+    }
+
+    // As in C, `a, b` evaluates both and is worth b. Where a comma separates
+    // things instead, as between arguments, parsing starts below this rule.
+    fn comma(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.assignment()?;
+        while self.match_any(&[TokenType::COMMA]) {
+            let operator = self.previous().clone();
+            let right = self.assignment()?;
+            expr = Expr::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    // The target is parsed as an ordinary expression, since which one it is
+    // only shows once the `=` turns up, and then turned into an assignment
+    // if it names a variable, a property or an element.
+    fn assignment(&mut self) -> Result<Expr<'a>, LoxError> {
+        let expr = self.conditional()?;
+        if self.match_any(&[TokenType::EQUAL]) {
+            let equals = self.previous().clone();
+            let value = self.assignment()?;
+            match expr.node {
+                ExprKind::Variable(name) => return Ok(Expr::assign(name, value)),
+                ExprKind::Get { object, name } => return Ok(Expr::set(*object, name, value)),
+                ExprKind::Index {
+                    object,
+                    bracket,
+                    index,
+                } => return Ok(Expr::index_set(*object, bracket, *index, value)),
+                _ => {}
+            }
+            // the parser knows exactly where it is, so there is nothing to
+            // synchronize; the error is only recorded
+            self.errors
+                .push(error(&equals, None, "Invalid assignment target."));
+        }
+        Ok(expr)
+    }
+
+    // The middle operand is bracketed by `?` and `:`, so it can be any
+    // expression; the last one is parsed at this level again, which makes
+    // `a ? b : c ? d : e` mean `a ? b : (c ? d : e)`.
+    fn conditional(&mut self) -> Result<Expr<'a>, LoxError> {
+        let condition = self.or()?;
+        if !self.match_any(&[TokenType::QUESTION]) {
+            return Ok(condition);
+        }
+        let then_expr = self.expression()?;
+        self.consume(
+            TokenType::COLON,
+            "Expect ':' after then branch of conditional expression.",
+        )?;
+        let else_expr = self.conditional()?;
+        Ok(Expr::ternary(condition, then_expr, else_expr))
+    }
+
+    fn or(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.and()?;
+        while self.match_any(&[TokenType::OR]) {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = Expr::logical(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.equality()?;
+        while self.match_any(&[TokenType::AND]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Expr::logical(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.comparison()?;
+        while self.match_any(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
+            let operator = self.previous().clone();
+            let right = self.comparison()?;
+            expr = Expr::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.term()?;
+        while self.match_any(&[
+            TokenType::GREATER,
+            TokenType::GREATER_EQUAL,
+            TokenType::LESS,
+            TokenType::LESS_EQUAL,
+        ]) {
+            let operator = self.previous().clone();
+            let right = self.term()?;
+            expr = Expr::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.factor()?;
+        while self.match_any(&[TokenType::MINUS, TokenType::PLUS]) {
+            let operator = self.previous().clone();
+            let right = self.factor()?;
+            expr = Expr::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.unary()?;
+        while self.match_any(&[TokenType::SLASH, TokenType::STAR, TokenType::PERCENT]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
+            expr = Expr::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr<'a>, LoxError> {
+        if self.match_any(&[TokenType::BANG, TokenType::MINUS]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
+            return Ok(Expr::unary(operator, right));
+        }
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr<'a>, LoxError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_any(&[TokenType::LEFT_PAREN]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_any(&[TokenType::DOT]) {
+                let name = self
+                    .consume(TokenType::IDENTIFIER, "Expect property name after '.'.")?
+                    .clone();
+                expr = Expr::get(expr, name);
+            } else if self.match_any(&[TokenType::LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let bracket = self
+                    .consume(TokenType::RIGHT_BRACKET, "Expect ']' after index.")?
+                    .clone();
+                expr = Expr::index(expr, bracket, index);
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    // Arguments start below the comma operator, whose commas would
+    // otherwise run them together.
+    fn finish_call(&mut self, callee: Expr<'a>) -> Result<Expr<'a>, LoxError> {
+        let mut arguments = Vec::new();
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                if arguments.len() == MAX_ARGUMENTS {
+                    // too many to run, but nothing the parser can't follow
+                    let message = format!("Can't have more than {MAX_ARGUMENTS} arguments.");
+                    self.errors.push(error(self.peek(), None, &message));
+                }
+                arguments.push(self.assignment()?);
+                if !self.match_any(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        let paren = self
+            .consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.")?
+            .clone();
+        Ok(Expr::call(callee, paren, arguments))
+    }
+
+    fn primary(&mut self) -> Result<Expr<'a>, LoxError> {
+        let start = self.peek().span.start;
+        let token = self.peek();
+        let value = match (token.token_type, &token.literal) {
+            (TokenType::FALSE, _) => LiteralValue::Bool(false),
+            (TokenType::TRUE, _) => LiteralValue::Bool(true),
+            (TokenType::NIL, _) => LiteralValue::Nil,
+            (TokenType::NUMBER, Some(Literal::Number(n))) => LiteralValue::Number(*n),
+            (TokenType::STRING, Some(Literal::Str(s))) => LiteralValue::Str(s.clone()),
+            (TokenType::SUPER, _) => {
+                let keyword = token.clone();
+                self.advance();
+                self.consume(TokenType::DOT, "Expect '.' after 'super'.")?;
+                let method = self
+                    .consume(TokenType::IDENTIFIER, "Expect superclass method name.")?
+                    .clone();
+                return Ok(self.spanned(start, ExprKind::Super { keyword, method }));
+            }
+            (TokenType::THIS, _) => {
+                let keyword = token.clone();
+                self.advance();
+                if self.class_depth == 0 {
+                    self.errors.push(error(
+                        &keyword,
+                        None,
+                        "Can't use 'this' outside of a class.",
+                    ));
+                } else if self.in_static_method {
+                    self.errors.push(error(
+                        &keyword,
+                        None,
+                        "Can't use 'this' in a static method.",
+                    ));
+                }
+                return Ok(self.spanned(start, ExprKind::This(keyword)));
+            }
+            (TokenType::IDENTIFIER, _) => {
+                let name = token.clone();
+                self.advance();
+                return Ok(Expr::variable(name));
+            }
+            (TokenType::FUN, _) => {
+                let keyword = token.clone();
+                self.advance();
+                self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'fun'.")?;
+                let (params, body) = self.function_body(FunctionKind::Lambda)?;
+                let lambda = ExprKind::Lambda {
+                    keyword,
+                    params,
+                    body,
+                };
+                return Ok(self.spanned(start, lambda));
+            }
+            (TokenType::LEFT_BRACKET, _) => {
+                self.advance();
+                let array = self.array()?;
+                return Ok(self.spanned(start, array));
+            }
+            (TokenType::LEFT_BRACE, _) => {
+                self.advance();
+                let map = self.map()?;
+                return Ok(self.spanned(start, map));
+            }
+            (TokenType::INTERPOLATION, _) => return self.interpolation(),
+            (TokenType::LEFT_PAREN, _) => {
+                self.advance();
+                let expr = self.expression()?;
+                self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?;
+                return Ok(self.spanned(start, ExprKind::Grouping(Box::new(expr))));
+            }
+            _ => match self.missing_left_operand()? {
+                Some(operand) => return Ok(operand),
+                None => return Err(error(self.peek(), None, "Expect expression.")),
+            },
+        };
+        self.advance();
+        Ok(self.spanned(start, ExprKind::Literal(value)))
+    }
+
+    // `"a ${x} b"` is parsed into the pieces of string and the expressions
+    // between them, in order, as an Interpolation rather than a chain of `+`
+    // so that what it means is left to the interpreter and the source syntax
+    // survives.
+    fn interpolation(&mut self) -> Result<Expr<'a>, LoxError> {
+        let start = self.peek().span.start;
+        let mut parts = vec![self.string_piece()];
+        loop {
+            parts.push(self.expression()?);
+            if !self.check(TokenType::INTERPOLATION) && !self.check(TokenType::STRING) {
+                let message = "Expect '}' after interpolated expression.";
+                return Err(error(self.peek(), None, message));
+            }
+            let done = self.check(TokenType::STRING);
+            parts.push(self.string_piece());
+            if done {
+                return Ok(self.spanned(start, ExprKind::Interpolation(parts)));
+            }
+        }
+    }
+
+    // The current STRING or INTERPOLATION token as a string literal.
+    fn string_piece(&mut self) -> Expr<'a> {
+        let piece = self.advance();
+        let value = match &piece.literal {
+            Some(Literal::Str(s)) => s.clone(),
+            _ => Cow::Borrowed(""),
+        };
+        let span = piece.span.clone();
+        Spanned::new(ExprKind::Literal(LiteralValue::Str(value)), span)
+    }
+
+    // Elements are separated as arguments are, and may be followed by one
+    // more comma.
+    fn array(&mut self) -> Result<ExprKind<'a>, LoxError> {
+        let mut elements = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACKET) {
+            elements.push(self.assignment()?);
+            if !self.match_any(&[TokenType::COMMA]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array elements.")?;
+        Ok(ExprKind::ArrayLiteral(elements))
+    }
+
+    // A brace only gets here where an expression is expected: at the start
+    // of a statement it is taken for a block, so a statement can't begin
+    // with a map literal unless it is put in parentheses.
+    fn map(&mut self) -> Result<ExprKind<'a>, LoxError> {
+        let mut entries = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) {
+            let key = self.assignment()?;
+            self.consume(TokenType::COLON, "Expect ':' after map key.")?;
+            let value = self.assignment()?;
+            entries.push((key, value));
+            if !self.match_any(&[TokenType::COMMA]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after map entries.")?;
+        Ok(ExprKind::MapLiteral(entries))
+    }
+
+    // A binary operator where an expression should start is reported as
+    // such, and its right operand parsed to carry on past it; the operand
+    // stands in for the whole expression.
+    fn missing_left_operand(&mut self) -> Result<Option<Expr<'a>>, LoxError> {
+        let operand: fn(&mut Self) -> Result<Expr<'a>, LoxError> = match self.peek().token_type {
+            TokenType::COMMA => Self::assignment,
+            TokenType::OR => Self::and,
+            TokenType::AND => Self::equality,
+            TokenType::BANG_EQUAL | TokenType::EQUAL_EQUAL => Self::comparison,
+            TokenType::GREATER
+            | TokenType::GREATER_EQUAL
+            | TokenType::LESS
+            | TokenType::LESS_EQUAL => Self::term,
+            TokenType::PLUS => Self::factor,
+            TokenType::SLASH | TokenType::STAR | TokenType::PERCENT => Self::unary,
+            _ => return Ok(None),
+        };
+        let operator = self.advance().clone();
+        let message = format!(
+            "Binary operator '{}' requires a left-hand operand.",
+            operator.lexeme
+        );
+        self.errors.push(error(&operator, None, &message));
+        operand(self).map(Some)
+    }
+
+    // Discards tokens up to the end of the statement the error was in: past
+    // a semicolon, or up to a keyword that starts a statement.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::SEMICOLON {
+                return;
+            }
+            match self.peek().token_type {
+                TokenType::CLASS
+                | TokenType::FUN
+                | TokenType::VAR
+                | TokenType::FOR
+                | TokenType::IF
+                | TokenType::WHILE
+                | TokenType::PRINT
+                | TokenType::RETURN => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    // Within a class body, discards tokens up to where the next method is
+    // likely to start: after the end of a method body, at a name followed by
+    // a parameter list or body or at `class`, or at the end of the class.
+    fn synchronize_method(&mut self) {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.peek().token_type {
+                TokenType::LEFT_BRACE => depth += 1,
+                TokenType::RIGHT_BRACE if depth == 0 => return,
+                TokenType::RIGHT_BRACE => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                TokenType::IDENTIFIER
+                    if depth == 0
+                        && (self.check_next(TokenType::LEFT_PAREN)
+                            || self.check_next(TokenType::LEFT_BRACE)) =>
+                {
+                    return
+                }
+                TokenType::CLASS if depth == 0 => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    // from the token `start` is the offset of to the last one consumed
+    fn spanned<T>(&self, start: usize, node: T) -> Spanned<T> {
+        Spanned::new(node, start..self.previous().span.end)
+    }
+
+    fn match_any(&mut self, types: &[TokenType]) -> bool {
+        if types.iter().any(|token_type| self.check(*token_type)) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token<'a>, LoxError> {
+        if self.check(token_type) {
+            return Ok(self.advance());
+        }
+        Err(error(self.peek(), Some(token_type), message))
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.peek().token_type == token_type
+    }
+
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
+    fn advance(&mut self) -> &Token<'a> {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::EOF
+    }
+
+    fn peek(&self) -> &Token<'a> {
+        &self.tokens[self.current]
+    }
+
+    fn previous(&self) -> &Token<'a> {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+}
+
+fn error(token: &Token, expected: Option<TokenType>, message: &str) -> LoxError {
+    LoxError::SyntaxError {
+        token: token.clone().into_owned(),
+        expected,
+        message: String::from(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{printer::AstPrinter, Scanner, ScannerOptions};
+    use std::ops::Range;
+
+    fn parse_with(source: &str, options: ScannerOptions) -> Result<String, Vec<String>> {
+        let tokens = Scanner::with_options(source, options)
+            .scan_tokens()
+            .unwrap();
+        let (statements, errors) = Parser::new(tokens).parse();
+        if !errors.is_empty() {
+            return Err(errors.into_iter().map(describe).collect());
+        }
+        let printed: Vec<_> = statements
+            .iter()
+            .map(|statement| AstPrinter.print_stmt(statement))
+            .collect();
+        Ok(printed.join("\n"))
+    }
+
+    // each statement printed by AstPrinter, one to a line
+    fn parse(source: &str) -> String {
+        parse_with(source, ScannerOptions::default()).unwrap()
+    }
+
+    // each syntax error as `line 'lexeme': message`
+    fn errors(source: &str) -> Vec<String> {
+        parse_with(source, ScannerOptions::default()).unwrap_err()
+    }
+
+    // the statements that parsed, printed, beside the lines of the errors
+    fn partial(source: &str) -> (Vec<String>, Vec<usize>) {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens).parse();
+        let printed = statements
+            .iter()
+            .map(|statement| AstPrinter.print_stmt(statement))
+            .collect();
+        let lines = errors
+            .iter()
+            .map(|error| match error {
+                LoxError::SyntaxError { token, .. } => token.line,
+                error => panic!("unexpected {error:?}"),
+            })
+            .collect();
+        (printed, lines)
+    }
+
+    // each error as the CLI reports it, without the caret line
+    fn rendered(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let (_, errors) = Parser::new(tokens).parse();
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    fn describe(error: LoxError) -> String {
+        match error {
+            LoxError::SyntaxError { token, message, .. } => {
+                format!("{} '{}': {message}", token.line, token.lexeme)
+            }
+            error => panic!("unexpected {error:?}"),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("1 + 2 * 3 == 7;"), "(; (== (+ 1 (* 2 3)) 7))");
+        assert_eq!(parse("1 - 2 - 3;"), "(; (- (- 1 2) 3))");
+        assert_eq!(
+            parse("!(1 < 2) != -3 >= 4;"),
+            "(; (!= (! (group (< 1 2))) (>= (- 3) 4)))"
+        );
+        assert_eq!(parse("\"a\" + nil == true;"), "(; (== (+ \"a\" nil) true))");
+    }
+
+    #[test]
+    fn modulo_binds_like_multiplication() {
+        assert_eq!(parse("1 + 7 % 4 * 2;"), "(; (+ 1 (* (% 7 4) 2)))");
+        assert_eq!(
+            errors("% 3;"),
+            ["1 '%': Binary operator '%' requires a left-hand operand."]
+        );
+    }
+
+    #[test]
+    fn expression_errors() {
+        assert_eq!(errors("(1 + 2;"), ["1 ';': Expect ')' after expression."]);
+        assert_eq!(
+            errors("+ 3;"),
+            ["1 '+': Binary operator '+' requires a left-hand operand."]
+        );
+        assert_eq!(errors("1 +;"), ["1 ';': Expect expression."]);
+    }
+
+    #[test]
+    fn interpolation() {
+        let interpolating = || ScannerOptions::default().interpolation(true);
+        assert_eq!(
+            parse_with("print \"a ${x} b ${y + 1}\";", interpolating()).unwrap(),
+            "(print \"a ${x} b ${(+ y 1)}\")"
+        );
+        assert_eq!(
+            parse_with("print \"${x y}\";", interpolating()).unwrap_err(),
+            ["1 'y': Expect '}' after interpolated expression."]
+        );
+        assert_eq!(
+            parse_with("print \"${\"in ${x}\"}!\";", interpolating()).unwrap(),
+            "(print \"${\"in ${x}\"}!\")"
+        );
+        // without the option `${` is part of the string
+        assert_eq!(parse("print \"a ${x} b\";"), "(print \"a ${x} b\")");
+    }
+
+    #[test]
+    fn errors_render_at_token_or_at_end() {
+        assert_eq!(
+            rendered("print (1;"),
+            ["[line 1:9] Error at ';': Expect ')' after expression."]
+        );
+        assert_eq!(
+            rendered("print 1"),
+            ["[line 1:8] Error at end: Expect ';' after value."]
+        );
+    }
+
+    #[test]
+    fn recovery_reports_each_error_once() {
+        assert_eq!(
+            errors("print (1; var = 2; 1 +;\nprint 4;"),
+            [
+                "1 ';': Expect ')' after expression.",
+                "1 '=': Expect variable name.",
+                "1 ';': Expect expression.",
+            ]
+        );
+        assert_eq!(
+            errors("print (1\nvar a = 2;\nprint 3 4;\nvar = 5;"),
+            [
+                "2 'var': Expect ')' after expression.",
+                "3 '4': Expect ';' after value.",
+                "4 '=': Expect variable name.",
+            ]
+        );
+    }
+
+    #[test]
+    fn good_statements_survive_bad_ones() {
+        let (statements, lines) =
+            partial("print 1;\nvar = 2;\nprint 3;\n(;\nprint 5;\nprint 6 7;\nprint 8;");
+        assert_eq!(
+            statements,
+            ["(print 1)", "(print 3)", "(print 5)", "(print 8)"]
+        );
+        assert_eq!(lines, [2, 4, 6]);
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(parse("print 1 + 2; 3;"), "(print (+ 1 2))\n(; 3)");
+        assert_eq!(errors("print 1"), ["1 '': Expect ';' after value."]);
+        assert_eq!(errors("1 + 2"), ["1 '': Expect ';' after expression."]);
+        assert_eq!(parse(""), "");
+        assert_eq!(parse("// nothing\n"), "");
+    }
+
+    #[test]
+    fn variable_declarations() {
+        assert_eq!(parse("var a; var b = 1 + 2;"), "(var a)\n(var b = (+ 1 2))");
+        assert_eq!(
+            errors("var class = 1;"),
+            ["1 'class': Expect variable name."]
+        );
+        assert_eq!(errors("var 123 = 4;"), ["1 '123': Expect variable name."]);
+        assert_eq!(
+            errors("var a = 1"),
+            ["1 '': Expect ';' after variable declaration."]
+        );
+        let (statements, lines) = partial("var 123 = 4;\nvar b = 5;");
+        assert_eq!(statements, ["(var b = 5)"]);
+        assert_eq!(lines, [1]);
+    }
+
+    #[test]
+    fn assignment() {
+        assert_eq!(parse("a = b = c;"), "(; (= a (= b c)))");
+        assert_eq!(parse("a = 1 + 2;"), "(; (= a (+ 1 2)))");
+        assert_eq!(errors("a + b = c;"), ["1 '=': Invalid assignment target."]);
+        // reported without synchronizing, so the statement still parses
+        let (statements, lines) = partial("a + b = c; print 1;");
+        assert_eq!(statements, ["(; (+ a b))", "(print 1)"]);
+        assert_eq!(lines, [1]);
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(parse("{}"), "(block)");
+        assert_eq!(
+            parse("{ { { print 1; } } }"),
+            "(block (block (block (print 1))))"
+        );
+        assert_eq!(
+            parse("{ var a = 1; print a; }"),
+            "(block (var a = 1) (print a))"
+        );
+        assert_eq!(
+            rendered("{ print 1;"),
+            ["[line 1:11] Error at end: Expect '}' after block."]
+        );
+    }
+
+    #[test]
+    fn if_statements() {
+        assert_eq!(parse("if (a) print 1;"), "(if a (print 1))");
+        // the else goes to the nearest if
+        assert_eq!(
+            parse("if (a) if (b) print 1; else print 2;"),
+            "(if a (if-else b (print 1) (print 2)))"
+        );
+        assert_eq!(errors("else print 1;"), ["1 'else': Expect expression."]);
+        assert_eq!(errors("if a) print 1;"), ["1 'a': Expect '(' after 'if'."]);
+        assert_eq!(
+            errors("if (a print 1;"),
+            ["1 'print': Expect ')' after if condition."]
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        // and binds tighter than or, and both group to the left
+        assert_eq!(parse("a or b and c;"), "(; (or a (and b c)))");
+        assert_eq!(parse("a or b or c;"), "(; (or (or a b) c))");
+        assert_eq!(parse("a and b and c;"), "(; (and (and a b) c))");
+        assert_eq!(
+            parse("a < 1 or b == 2 and !c;"),
+            "(; (or (< a 1) (and (== b 2) (! c))))"
+        );
+    }
+
+    #[test]
+    fn while_loops() {
+        assert_eq!(
+            parse("while (a) { print 1; }"),
+            "(while a (block (print 1)))"
+        );
+        assert_eq!(parse("while (a) print 1;"), "(while a (print 1))");
+        assert_eq!(
+            errors("while a) print 1;"),
+            ["1 'a': Expect '(' after 'while'."]
+        );
+        assert_eq!(
+            errors("while (a print 1;"),
+            ["1 'print': Expect ')' after condition."]
+        );
+        assert_eq!(errors("while () print 1;"), ["1 ')': Expect expression."]);
+    }
+
+    #[test]
+    fn for_loops_desugar_to_while() {
+        assert_eq!(
+            parse("for (var i = 0; i < 3; i = i + 1) print i;"),
+            "(block (var i = 0) (while (< i 3) (print i) (= i (+ i 1))))"
+        );
+        assert_eq!(
+            parse("for (i = 0; i < 3;) print i;"),
+            "(block (; (= i 0)) (while (< i 3) (print i)))"
+        );
+        assert_eq!(
+            parse("for (; i < 3; i = i + 1) print i;"),
+            "(while (< i 3) (print i) (= i (+ i 1)))"
+        );
+        assert_eq!(parse("for (;;) print 1;"), "(while true (print 1))");
+        assert_eq!(
+            errors("for var i;;) print 1;")[0],
+            "1 'var': Expect '(' after 'for'."
+        );
+        assert_eq!(
+            errors("for (var i = 0 i < 3;) print 1;")[0],
+            "1 'i': Expect ';' after variable declaration."
+        );
+        assert_eq!(
+            errors("for (; i < 3 i) print 1;"),
+            ["1 'i': Expect ';' after loop condition."]
+        );
+        assert_eq!(
+            errors("for (;; i = 1 print 1;"),
+            ["1 'print': Expect ')' after for clauses."]
+        );
+    }
+
+    #[test]
+    fn break_inside_loops_only() {
+        assert_eq!(parse("while (a) break;"), "(while a (break))");
+        assert_eq!(parse("for (;;) break;"), "(while true (break))");
+        assert_eq!(
+            parse("while (a) { for (;;) { break; } break; }"),
+            "(while a (block (while true (block (break))) (break)))"
+        );
+        assert_eq!(
+            errors("break;"),
+            ["1 'break': Must be inside a loop to use 'break'."]
+        );
+        // a function body starts outside any loop, even one inside a loop
+        assert_eq!(
+            errors("while (a) { fun f() { break; } }"),
+            ["1 'break': Must be inside a loop to use 'break'."]
+        );
+    }
+
+    #[test]
+    fn continue_keeps_the_increment() {
+        // the increment stays outside the body, where a continue still
+        // reaches it, rather than being appended to the body's block
+        assert_eq!(
+            parse("for (var i = 0; i < 3; i = i + 1) { if (i == 1) continue; print i; }"),
+            "(block (var i = 0) (while (< i 3) \
+             (block (if (== i 1) (continue)) (print i)) (= i (+ i 1))))"
+        );
+        assert_eq!(parse("while (a) continue;"), "(while a (continue))");
+        assert_eq!(
+            errors("continue;"),
+            ["1 'continue': Must be inside a loop to use 'continue'."]
+        );
+        assert_eq!(
+            errors("while (a) { fun f() { continue; } }"),
+            ["1 'continue': Must be inside a loop to use 'continue'."]
+        );
+    }
+
+    #[test]
+    fn comma_operator() {
+        assert_eq!(parse("(a = 1, b = 2);"), "(; (group (, (= a 1) (= b 2))))");
+        assert_eq!(parse("a, b, c;"), "(; (, (, a b) c))");
+        // arguments are parsed below the comma, unless grouped
+        assert_eq!(parse("f(a, b);"), "(; (call f a b))");
+        assert_eq!(parse("f((a, b));"), "(; (call f (group (, a b))))");
+    }
+
+    #[test]
+    fn conditional_operator() {
+        assert_eq!(parse("a ? b : c ? d : e;"), "(; (?: a b (?: c d e)))");
+        assert_eq!(parse("x = a ? b : c;"), "(; (= x (?: a b c)))");
+        assert_eq!(parse("a or b ? c : d;"), "(; (?: (or a b) c d))");
+        assert_eq!(
+            errors("a ? b c;"),
+            ["1 'c': Expect ':' after then branch of conditional expression."]
+        );
+    }
+
+    #[test]
+    fn missing_left_operands() {
+        let operators = [
+            ",", "or", "and", "==", "!=", "<", "<=", ">", ">=", "+", "*", "/", "%",
+        ];
+        for operator in operators {
+            // the right operand stands in for the expression, and the next
+            // statement parses as usual
+            let (statements, lines) = partial(&format!("{operator} 1;\nprint 2;"));
+            assert_eq!(statements, ["(; 1)", "(print 2)"], "{operator}");
+            assert_eq!(lines, [1], "{operator}");
+            assert_eq!(
+                errors(&format!("{operator} 1;")),
+                [format!(
+                    "1 '{operator}': Binary operator '{operator}' requires a left-hand operand."
+                )]
+            );
+        }
+        let (statements, lines) = partial("print , 1; print 2;");
+        assert_eq!(statements, ["(print 1)", "(print 2)"]);
+        assert_eq!(lines, [1]);
+        assert_eq!(parse("- 1;"), "(; (- 1))");
+    }
+
+    #[test]
+    fn calls() {
+        assert_eq!(parse("f();"), "(; (call f))");
+        assert_eq!(parse("f(1);"), "(; (call f 1))");
+        assert_eq!(parse("f(1, a, \"b\");"), "(; (call f 1 a \"b\"))");
+        assert_eq!(parse("f(1)(2);"), "(; (call (call f 1) 2))");
+        assert_eq!(errors("f(1,);"), ["1 ')': Expect expression."]);
+        assert_eq!(errors("f(1 2);"), ["1 '2': Expect ')' after arguments."]);
+        assert_eq!(
+            rendered("f(1, 2"),
+            ["[line 1:7] Error at end: Expect ')' after arguments."]
+        );
+    }
+
+    #[test]
+    fn at_most_255_arguments() {
+        let call = |count| format!("f({});", vec!["1"; count].join(", "));
+        assert!(rendered(&call(255)).is_empty());
+        // reported at the 256th argument, without giving up on the call
+        assert_eq!(
+            rendered(&call(256)),
+            ["[line 1:768] Error at '1': Can't have more than 255 arguments."]
+        );
+        let (statements, _) = partial(&call(256));
+        assert_eq!(statements.len(), 1);
+    }
+
+    #[test]
+    fn function_declarations() {
+        assert_eq!(parse("fun f() {}"), "(fun f ())");
+        assert_eq!(parse("fun f(a, b) { print a; }"), "(fun f (a b) (print a))");
+        assert_eq!(parse("fun f() { fun g(a) {} }"), "(fun f () (fun g (a)))");
+        // without a name it could only be a function expression
+        assert_eq!(errors("fun 1() {}"), ["1 '1': Expect '(' after 'fun'."]);
+        assert_eq!(errors("fun f(1) {}"), ["1 '1': Expect parameter name."]);
+        assert_eq!(
+            errors("fun f(a b) {}"),
+            ["1 'b': Expect ')' after parameters."]
+        );
+        assert_eq!(
+            errors("fun f() print 1;"),
+            ["1 'print': Expect '{' before function body."]
+        );
+        let parameters = vec!["a"; 256].join(", ");
+        assert_eq!(
+            rendered(&format!("fun f({parameters}) {{}}")),
+            ["[line 1:772] Error at 'a': Can't have more than 255 parameters."]
+        );
+        // an error in the body still leaves the function declared
+        let (statements, lines) = partial("fun f() { print; }\nprint 2;");
+        assert_eq!(statements, ["(fun f ())", "(print 2)"]);
+        assert_eq!(lines, [1]);
+    }
+
+    #[test]
+    fn return_statements() {
+        assert_eq!(
+            parse("fun f() { return; return a; }"),
+            "(fun f () (return) (return a))"
+        );
+        // rejecting a return outside any function is left to a later pass
+        assert_eq!(parse("return 1 + 2;"), "(return (+ 1 2))");
+        assert_eq!(parse("return;"), "(return)");
+        assert_eq!(errors("return 1"), ["1 '': Expect ';' after return value."]);
+    }
+
+    #[test]
+    fn function_expressions() {
+        assert_eq!(
+            parse("thrice(fun (i) { print i; });"),
+            "(; (call thrice (fun (i) (print i))))"
+        );
+        // a function expression can be called where it is written
+        assert_eq!(
+            parse("fun (x) { print x; }(3);"),
+            "(; (call (fun (x) (print x)) 3))"
+        );
+        assert_eq!(parse("var f = fun () {};"), "(var f = (fun ()))");
+        assert_eq!(
+            parse("fun add(a, b) { return a + b; }"),
+            "(fun add (a b) (return (+ a b)))"
+        );
+    }
+
+    #[test]
+    fn class_declarations() {
+        assert_eq!(parse("class A {}"), "(class A)");
+        assert_eq!(
+            parse("class A { b() {} c(x, y) { return x; } }"),
+            "(class A (fun b ()) (fun c (x y) (return x)))"
+        );
+        assert_eq!(errors("class {}"), ["1 '{': Expect class name."]);
+        assert_eq!(
+            errors("class A b() {} }"),
+            ["1 'b': Expect '{' before class body."]
+        );
+        assert_eq!(
+            errors("class A { b() {}"),
+            ["1 '': Expect '}' after class body."]
+        );
+        assert_eq!(
+            errors("class A { b() {} 1 c() {} }"),
+            ["1 '1': Expect method name."]
+        );
+        // an error inside one method leaves the next to parse
+        let (statements, lines) = partial("class A { b() { print; } c() {} }\nprint 1;");
+        assert_eq!(statements, ["(class A (fun b ()) (fun c ()))", "(print 1)"]);
+        assert_eq!(lines, [1]);
+    }
+
+    #[test]
+    fn this_inside_classes_only() {
+        assert_eq!(
+            parse("class A { b() { print this.name; } }"),
+            "(class A (fun b () (print (. this name))))"
+        );
+        // a function nested in a method still has the method's instance
+        assert_eq!(
+            parse("class A { b() { fun f() { return this; } } }"),
+            "(class A (fun b () (fun f () (return this))))"
+        );
+        assert_eq!(
+            errors("this;"),
+            ["1 'this': Can't use 'this' outside of a class."]
+        );
+        assert_eq!(
+            errors("fun f() {\n  this;\n}"),
+            ["2 'this': Can't use 'this' outside of a class."]
+        );
+    }
+
+    #[test]
+    fn superclasses_and_super() {
+        assert_eq!(parse("class A < B {}"), "(class A < B)");
+        // inheriting from itself is for a later pass to reject
+        assert_eq!(parse("class A < A {}"), "(class A < A)");
+        assert_eq!(
+            parse("class A < B { init() { super.init(); } }"),
+            "(class A < B (fun init () (; (call (super init)))))"
+        );
+        assert_eq!(errors("class A < {}"), ["1 '{': Expect superclass name."]);
+        assert_eq!(
+            errors("class A < B { b() { super; } }"),
+            ["1 ';': Expect '.' after 'super'."]
+        );
+        assert_eq!(
+            errors("class A < B { b() { super.; } }"),
+            ["1 ';': Expect superclass method name."]
+        );
+    }
+
+    #[test]
+    fn static_methods() {
+        assert_eq!(
+            parse("class Math { class square(n) { return n * n; } }\nMath.square(3);"),
+            "(class Math (static square (n) (return (* n n))))\n\
+             (; (call (. Math square) 3))"
+        );
+        assert_eq!(
+            errors("class A { class b() { this; } }"),
+            ["1 'this': Can't use 'this' in a static method."]
+        );
+    }
+
+    #[test]
+    fn getters() {
+        assert_eq!(
+            parse("class Circle { area { return 3 * this.r * this.r; } }\nprint c.area;"),
+            "(class Circle (get area () (return (* (* 3 (. this r)) (. this r)))))\n\
+             (print (. c area))"
+        );
+        // outside a class body a name and a brace are two statements
+        assert_eq!(
+            errors("area { return 1; }")[0],
+            "1 '{': Expect ';' after expression."
+        );
+    }
+
+    #[test]
+    fn properties() {
+        assert_eq!(parse("a.b.c;"), "(; (. (. a b) c))");
+        assert_eq!(parse("a.b.c = v;"), "(; (= (. (. a b) c) v))");
+        assert_eq!(parse("f().x = 1;"), "(; (= (. (call f) x) 1))");
+        assert_eq!(parse("(a).x;"), "(; (. (group a) x))");
+        assert_eq!(errors("a.;"), ["1 ';': Expect property name after '.'."]);
+        assert_eq!(errors("(a) = 1;"), ["1 '=': Invalid assignment target."]);
+        assert_eq!(errors("f() = 1;"), ["1 '=': Invalid assignment target."]);
+    }
+
+    #[test]
+    fn arrays_and_indexing() {
+        assert_eq!(
+            parse("var xs = [1, 2, 3]; print xs[0]; xs[1] = 5;"),
+            "(var xs = (array 1 2 3))\n(print (index xs 0))\n(; (= (index xs 1) 5))"
+        );
+        assert_eq!(parse("[];"), "(; (array))");
+        assert_eq!(parse("[[1, 2], [3]];"), "(; (array (array 1 2) (array 3)))");
+        assert_eq!(parse("[1, 2,];"), "(; (array 1 2))");
+        assert_eq!(parse("m[0][1];"), "(; (index (index m 0) 1))");
+        assert_eq!(errors("xs[0;"), ["1 ';': Expect ']' after index."]);
+        assert_eq!(
+            errors("[1, 2;"),
+            ["1 ';': Expect ']' after array elements."]
+        );
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(parse("var m = {};"), "(var m = (map))");
+        assert_eq!(
+            parse("var m = {\"a\": 1, 2: \"b\",};"),
+            "(var m = (map (\"a\" 1) (2 \"b\")))"
+        );
+        assert_eq!(
+            parse("var m = {\"a\": {\"b\": 1}};"),
+            "(var m = (map (\"a\" (map (\"b\" 1)))))"
+        );
+        assert_eq!(
+            parse("print m[\"a\"]; m[\"a\"] = 2;"),
+            "(print (index m \"a\"))\n(; (= (index m \"a\") 2))"
+        );
+        assert_eq!(
+            errors("var m = {\"a\" 1};"),
+            ["1 '1': Expect ':' after map key."]
+        );
+        // a brace starting a statement always starts a block
+        assert_eq!(parse("{}"), "(block)");
+        assert_eq!(
+            errors("{\"a\": 1};")[0],
+            "1 ':': Expect ';' after expression."
+        );
+    }
+
+    #[test]
+    fn spans_cover_the_source_of_each_node() {
+        let source = "print (1 + 2) * -x;\n{ var a = 1;\n  print a; }\nf(a, g(b), 3);";
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        let text = |span: &Range<usize>| &source[span.clone()];
+
+        assert_eq!(text(&statements[0].span), "print (1 + 2) * -x;");
+        let StmtKind::Print(product) = &statements[0].node else {
+            panic!("not a print: {:?}", statements[0]);
+        };
+        assert_eq!(text(&product.span), "(1 + 2) * -x");
+        let ExprKind::Binary { left, right, .. } = &product.node else {
+            panic!("not a binary: {product:?}");
+        };
+        assert_eq!(text(&left.span), "(1 + 2)");
+        assert_eq!(text(&right.span), "-x");
+        let ExprKind::Grouping(sum) = &left.node else {
+            panic!("not a grouping: {left:?}");
+        };
+        assert_eq!(text(&sum.span), "1 + 2");
+
+        assert_eq!(text(&statements[1].span), "{ var a = 1;\n  print a; }");
+        let StmtKind::Block(inner) = &statements[1].node else {
+            panic!("not a block: {:?}", statements[1]);
+        };
+        assert_eq!(text(&inner[0].span), "var a = 1;");
+        assert_eq!(text(&inner[1].span), "print a;");
+
+        let StmtKind::Expression(call) = &statements[2].node else {
+            panic!("not an expression: {:?}", statements[2]);
+        };
+        assert_eq!(text(&call.span), "f(a, g(b), 3)");
+        let ExprKind::Call { arguments, .. } = &call.node else {
+            panic!("not a call: {call:?}");
+        };
+        let arguments: Vec<_> = arguments.iter().map(|arg| text(&arg.span)).collect();
+        assert_eq!(arguments, ["a", "g(b)", "3"]);
+    }
+}
@@ -0,0 +1,1563 @@
+use crate::{
+    ast::{
+        Expr, ExprKind, ExprVisitor, Function, LiteralValue, LoopSyntax, Spanned, Stmt, StmtKind,
+        StmtVisitor,
+    },
+    Literal, Token, TokenType,
+};
+use std::ops::Range;
+
+// Prints an expression fully parenthesized, operators first, as in the book:
+// `-123 * (45.67)` becomes `(* (- 123) (group 45.67))`. Statements are
+// printed the same way, an expression statement as `(; expr)`.
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn print(&mut self, expr: &Expr) -> String {
+        expr.accept(self)
+    }
+
+    pub fn print_stmt(&mut self, stmt: &Stmt) -> String {
+        stmt.accept(self)
+    }
+
+    fn function(&mut self, name: &str, params: &[Token], body: &[Stmt]) -> String {
+        let params: Vec<_> = params.iter().map(|param| &*param.lexeme).collect();
+        let mut out = format!("({name} ({})", params.join(" "));
+        for statement in body {
+            out.push(' ');
+            out.push_str(&statement.accept(self));
+        }
+        out.push(')');
+        out
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> String {
+        let mut out = format!("({name}");
+        for expr in exprs {
+            out.push(' ');
+            out.push_str(&expr.accept(self));
+        }
+        out.push(')');
+        out
+    }
+}
+
+impl<'a> ExprVisitor<'a, String> for AstPrinter {
+    fn visit_array_literal(&mut self, elements: &[Expr<'a>]) -> String {
+        let elements: Vec<_> = elements.iter().collect();
+        self.parenthesize("array", &elements)
+    }
+
+    fn visit_assign(&mut self, name: &Token<'a>, value: &Expr<'a>) -> String {
+        self.parenthesize(&format!("= {}", name.lexeme), &[value])
+    }
+
+    fn visit_binary(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr<'a>,
+        _paren: &Token<'a>,
+        arguments: &[Expr<'a>],
+    ) -> String {
+        let mut exprs = vec![callee];
+        exprs.extend(arguments);
+        self.parenthesize("call", &exprs)
+    }
+
+    fn visit_get(&mut self, object: &Expr<'a>, name: &Token<'a>) -> String {
+        format!("(. {} {})", object.accept(self), name.lexeme)
+    }
+
+    fn visit_index(&mut self, object: &Expr<'a>, _bracket: &Token<'a>, index: &Expr<'a>) -> String {
+        self.parenthesize("index", &[object, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        value: &Expr<'a>,
+    ) -> String {
+        format!(
+            "(= {} {})",
+            self.visit_index(object, bracket, index),
+            value.accept(self)
+        )
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr<'a>) -> String {
+        self.parenthesize("group", &[expression])
+    }
+
+    fn visit_interpolation(&mut self, parts: &[Expr<'a>]) -> String {
+        interpolation(self, parts, str::to_owned)
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token<'a>,
+        params: &[Token<'a>],
+        body: &[Stmt<'a>],
+    ) -> String {
+        self.function("fun", params, body)
+    }
+
+    fn visit_literal(&mut self, value: &LiteralValue<'a>) -> String {
+        literal(value)
+    }
+
+    fn visit_map_literal(&mut self, entries: &[(Expr<'a>, Expr<'a>)]) -> String {
+        let mut out = String::from("(map");
+        for (key, value) in entries {
+            out.push_str(&format!(" ({} {})", key.accept(self), value.accept(self)));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_logical(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expr<'a>,
+        then_expr: &Expr<'a>,
+        else_expr: &Expr<'a>,
+    ) -> String {
+        self.parenthesize("?:", &[condition, then_expr, else_expr])
+    }
+
+    fn visit_set(&mut self, object: &Expr<'a>, name: &Token<'a>, value: &Expr<'a>) -> String {
+        format!(
+            "(= {} {})",
+            self.visit_get(object, name),
+            value.accept(self)
+        )
+    }
+
+    fn visit_super(&mut self, _keyword: &Token<'a>, method: &Token<'a>) -> String {
+        format!("(super {})", method.lexeme)
+    }
+
+    fn visit_this(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("this")
+    }
+
+    fn visit_unary(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        self.parenthesize(&operator.lexeme, &[right])
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) -> String {
+        name.lexeme.to_string()
+    }
+}
+
+impl<'a> StmtVisitor<'a, String> for AstPrinter {
+    fn visit_block(&mut self, statements: &[Stmt<'a>]) -> String {
+        let mut out = String::from("(block");
+        for statement in statements {
+            out.push(' ');
+            out.push_str(&statement.accept(self));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_break(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("(break)")
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token<'a>,
+        superclass: Option<&Expr<'a>>,
+        methods: &[Function<'a>],
+    ) -> String {
+        let mut out = format!("(class {}", name.lexeme);
+        if let Some(superclass) = superclass {
+            out.push_str(&format!(" < {}", superclass.accept(self)));
+        }
+        for method in methods {
+            out.push(' ');
+            out.push_str(&self.visit_function(method));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_continue(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("(continue)")
+    }
+
+    fn visit_expression(&mut self, expression: &Expr<'a>) -> String {
+        self.parenthesize(";", &[expression])
+    }
+
+    fn visit_function(&mut self, function: &Function<'a>) -> String {
+        let kind = match (function.is_static, function.is_getter) {
+            (false, false) => "fun",
+            (false, true) => "get",
+            (true, false) => "static",
+            (true, true) => "static get",
+        };
+        self.function(
+            &format!("{kind} {}", function.name.lexeme),
+            &function.params,
+            &function.body,
+        )
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr<'a>,
+        then_branch: &Stmt<'a>,
+        else_branch: Option<&Stmt<'a>>,
+    ) -> String {
+        match else_branch {
+            Some(else_branch) => format!(
+                "(if-else {} {} {})",
+                condition.accept(self),
+                then_branch.accept(self),
+                else_branch.accept(self)
+            ),
+            None => format!(
+                "(if {} {})",
+                condition.accept(self),
+                then_branch.accept(self)
+            ),
+        }
+    }
+
+    fn visit_print(&mut self, expression: &Expr<'a>) -> String {
+        self.parenthesize("print", &[expression])
+    }
+
+    fn visit_return(&mut self, _keyword: &Token<'a>, value: Option<&Expr<'a>>) -> String {
+        match value {
+            Some(value) => self.parenthesize("return", &[value]),
+            None => String::from("(return)"),
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token<'a>, initializer: Option<&Expr<'a>>) -> String {
+        match initializer {
+            Some(initializer) => {
+                self.parenthesize(&format!("var {} =", name.lexeme), &[initializer])
+            }
+            None => format!("(var {})", name.lexeme),
+        }
+    }
+
+    fn visit_while(
+        &mut self,
+        condition: &Expr<'a>,
+        body: &Stmt<'a>,
+        increment: Option<&Expr<'a>>,
+        _syntax: LoopSyntax,
+    ) -> String {
+        match increment {
+            Some(increment) => format!(
+                "(while {} {} {})",
+                condition.accept(self),
+                body.accept(self),
+                increment.accept(self)
+            ),
+            None => format!("(while {} {})", condition.accept(self), body.accept(self)),
+        }
+    }
+}
+
+// Prints an expression in Reverse Polish notation, operands before their
+// operator: `(1 + 2) * (4 - 3)` becomes `1 2 + 4 3 - *`. Groupings leave no
+// trace, the order already says it all. Unary minus prints as `neg`, to tell
+// it from subtraction (`~` is taken by the bitwise not); `!` prints as is.
+// A call is its callee and arguments, then `call/N` for N arguments; a
+// property is its object and name, then `.`, or `.=` after the value to set,
+// and indexing the same with `[]` and `[]=`. An array literal is its
+// elements, then `array/N`, and a map literal its keys and values, then
+// `map/N` for N pairs.
+pub struct RpnPrinter;
+
+impl RpnPrinter {
+    pub fn print(&mut self, expr: &Expr) -> String {
+        expr.accept(self)
+    }
+}
+
+impl<'a> ExprVisitor<'a, String> for RpnPrinter {
+    fn visit_array_literal(&mut self, elements: &[Expr<'a>]) -> String {
+        let mut out = String::new();
+        for element in elements {
+            out.push_str(&element.accept(self));
+            out.push(' ');
+        }
+        out.push_str(&format!("array/{}", elements.len()));
+        out
+    }
+
+    fn visit_assign(&mut self, name: &Token<'a>, value: &Expr<'a>) -> String {
+        format!("{} {} =", name.lexeme, value.accept(self))
+    }
+
+    fn visit_binary(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        format!(
+            "{} {} {}",
+            left.accept(self),
+            right.accept(self),
+            operator.lexeme
+        )
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr<'a>,
+        _paren: &Token<'a>,
+        arguments: &[Expr<'a>],
+    ) -> String {
+        let mut out = callee.accept(self);
+        for argument in arguments {
+            out.push(' ');
+            out.push_str(&argument.accept(self));
+        }
+        out.push_str(&format!(" call/{}", arguments.len()));
+        out
+    }
+
+    fn visit_get(&mut self, object: &Expr<'a>, name: &Token<'a>) -> String {
+        format!("{} {} .", object.accept(self), name.lexeme)
+    }
+
+    fn visit_index(&mut self, object: &Expr<'a>, _bracket: &Token<'a>, index: &Expr<'a>) -> String {
+        format!("{} {} []", object.accept(self), index.accept(self))
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr<'a>,
+        _bracket: &Token<'a>,
+        index: &Expr<'a>,
+        value: &Expr<'a>,
+    ) -> String {
+        format!(
+            "{} {} {} []=",
+            object.accept(self),
+            index.accept(self),
+            value.accept(self)
+        )
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr<'a>) -> String {
+        expression.accept(self)
+    }
+
+    // the expressions inside are printed in RPN too
+    fn visit_interpolation(&mut self, parts: &[Expr<'a>]) -> String {
+        interpolation(self, parts, str::to_owned)
+    }
+
+    // a function's body is statements, which have no RPN to speak of
+    fn visit_lambda(
+        &mut self,
+        keyword: &Token<'a>,
+        params: &[Token<'a>],
+        body: &[Stmt<'a>],
+    ) -> String {
+        AstPrinter.visit_lambda(keyword, params, body)
+    }
+
+    fn visit_literal(&mut self, value: &LiteralValue<'a>) -> String {
+        literal(value)
+    }
+
+    fn visit_map_literal(&mut self, entries: &[(Expr<'a>, Expr<'a>)]) -> String {
+        let mut out = String::new();
+        for (key, value) in entries {
+            out.push_str(&format!("{} {} ", key.accept(self), value.accept(self)));
+        }
+        out.push_str(&format!("map/{}", entries.len()));
+        out
+    }
+
+    fn visit_logical(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        self.visit_binary(left, operator, right)
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expr<'a>,
+        then_expr: &Expr<'a>,
+        else_expr: &Expr<'a>,
+    ) -> String {
+        format!(
+            "{} {} {} ?:",
+            condition.accept(self),
+            then_expr.accept(self),
+            else_expr.accept(self)
+        )
+    }
+
+    fn visit_set(&mut self, object: &Expr<'a>, name: &Token<'a>, value: &Expr<'a>) -> String {
+        format!(
+            "{} {} {} .=",
+            object.accept(self),
+            name.lexeme,
+            value.accept(self)
+        )
+    }
+
+    fn visit_super(&mut self, _keyword: &Token<'a>, method: &Token<'a>) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    fn visit_this(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("this")
+    }
+
+    fn visit_unary(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        match operator.token_type {
+            TokenType::MINUS => format!("{} neg", right.accept(self)),
+            _ => format!("{} {}", right.accept(self), operator.lexeme),
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) -> String {
+        name.lexeme.to_string()
+    }
+}
+
+// A string with each expression interpolated into it printed by `visitor`
+// inside `${...}`, and each piece of the string as `piece` has it.
+fn interpolation<'a>(
+    visitor: &mut impl ExprVisitor<'a, String>,
+    parts: &[Expr<'a>],
+    piece: fn(&str) -> String,
+) -> String {
+    let mut out = String::from("\"");
+    for (i, part) in parts.iter().enumerate() {
+        match &part.node {
+            ExprKind::Literal(LiteralValue::Str(s)) if i % 2 == 0 => out.push_str(&piece(s)),
+            _ => out.push_str(&format!("${{{}}}", part.accept(visitor))),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn literal(value: &LiteralValue) -> String {
+    match value {
+        // as jlox prints numbers, without a fractional part if it is zero
+        LiteralValue::Number(n) => format!("{n}"),
+        LiteralValue::Str(s) => format!("\"{s}\""),
+        LiteralValue::Bool(b) => format!("{b}"),
+        LiteralValue::Nil => String::from("nil"),
+    }
+}
+
+// Prints statements back as Lox source, one to a line, blocks and class
+// bodies indented by four spaces. Groupings are kept as written and nothing
+// else is parenthesized, so a tree from the parser prints as source that
+// parses to the same tree. A loop parsed from a `for` is printed as that
+// `for`, initializer and all, and only such a loop.
+pub fn format_program(statements: &[Stmt]) -> String {
+    let mut formatter = Formatter { indent: 0 };
+    let mut out = String::new();
+    for statement in statements {
+        out.push_str(&statement.accept(&mut formatter));
+        out.push('\n');
+    }
+    out
+}
+
+// Statements are formatted without their own indent or line ending, the
+// lines inside them already indented to `indent` and deeper.
+struct Formatter {
+    indent: usize,
+}
+
+impl Formatter {
+    fn pad(&self) -> String {
+        "    ".repeat(self.indent)
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> String {
+        if statements.is_empty() {
+            return String::from("{}");
+        }
+        self.indent += 1;
+        let mut out = String::from("{\n");
+        for statement in statements {
+            out.push_str(&format!("{}{}\n", self.pad(), statement.accept(self)));
+        }
+        self.indent -= 1;
+        out.push_str(&format!("{}}}", self.pad()));
+        out
+    }
+
+    // A loop or if body: a block on the same line, anything else on a line
+    // of its own, one level in.
+    fn body(&mut self, stmt: &Stmt) -> String {
+        match &stmt.node {
+            StmtKind::Block(statements) if !desugared_for(statements) => {
+                format!(" {}", self.block(statements))
+            }
+            _ => {
+                self.indent += 1;
+                let out = format!("\n{}{}", self.pad(), stmt.accept(self));
+                self.indent -= 1;
+                out
+            }
+        }
+    }
+
+    fn for_clauses(
+        &mut self,
+        initializer: &str,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> String {
+        // one left out has an empty span where it would have been
+        let condition = if condition.span.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", condition.accept(self))
+        };
+        let increment = match increment {
+            Some(increment) => format!(" {}", increment.accept(self)),
+            None => String::new(),
+        };
+        format!(
+            "for ({initializer}{condition};{increment}){}",
+            self.body(body)
+        )
+    }
+
+    fn function(&mut self, name: &str, params: &[Token], body: &[Stmt]) -> String {
+        let params: Vec<_> = params.iter().map(|param| &*param.lexeme).collect();
+        format!("{name}({}) {}", params.join(", "), self.block(body))
+    }
+
+    fn list<'a>(&mut self, exprs: impl IntoIterator<Item = &'a Expr<'a>>) -> String {
+        let exprs: Vec<_> = exprs.into_iter().map(|expr| expr.accept(self)).collect();
+        exprs.join(", ")
+    }
+}
+
+impl<'a> ExprVisitor<'a, String> for Formatter {
+    fn visit_array_literal(&mut self, elements: &[Expr<'a>]) -> String {
+        format!("[{}]", self.list(elements))
+    }
+
+    fn visit_assign(&mut self, name: &Token<'a>, value: &Expr<'a>) -> String {
+        format!("{} = {}", name.lexeme, value.accept(self))
+    }
+
+    fn visit_binary(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        let left = left.accept(self);
+        let right = right.accept(self);
+        match operator.token_type {
+            TokenType::COMMA => format!("{left}, {right}"),
+            _ => format!("{left} {} {right}", operator.lexeme),
+        }
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr<'a>,
+        _paren: &Token<'a>,
+        arguments: &[Expr<'a>],
+    ) -> String {
+        format!("{}({})", callee.accept(self), self.list(arguments))
+    }
+
+    fn visit_get(&mut self, object: &Expr<'a>, name: &Token<'a>) -> String {
+        format!("{}.{}", object.accept(self), name.lexeme)
+    }
+
+    fn visit_index(&mut self, object: &Expr<'a>, _bracket: &Token<'a>, index: &Expr<'a>) -> String {
+        format!("{}[{}]", object.accept(self), index.accept(self))
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        value: &Expr<'a>,
+    ) -> String {
+        format!(
+            "{} = {}",
+            self.visit_index(object, bracket, index),
+            value.accept(self)
+        )
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr<'a>) -> String {
+        format!("({})", expression.accept(self))
+    }
+
+    fn visit_interpolation(&mut self, parts: &[Expr<'a>]) -> String {
+        interpolation(self, parts, escape)
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token<'a>,
+        params: &[Token<'a>],
+        body: &[Stmt<'a>],
+    ) -> String {
+        self.function("fun ", params, body)
+    }
+
+    fn visit_literal(&mut self, value: &LiteralValue<'a>) -> String {
+        match value {
+            LiteralValue::Str(s) => format!("\"{}\"", escape(s)),
+            _ => literal(value),
+        }
+    }
+
+    fn visit_map_literal(&mut self, entries: &[(Expr<'a>, Expr<'a>)]) -> String {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key.accept(self), value.accept(self)))
+            .collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+
+    fn visit_logical(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        self.visit_binary(left, operator, right)
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expr<'a>,
+        then_expr: &Expr<'a>,
+        else_expr: &Expr<'a>,
+    ) -> String {
+        format!(
+            "{} ? {} : {}",
+            condition.accept(self),
+            then_expr.accept(self),
+            else_expr.accept(self)
+        )
+    }
+
+    fn visit_set(&mut self, object: &Expr<'a>, name: &Token<'a>, value: &Expr<'a>) -> String {
+        format!("{} = {}", self.visit_get(object, name), value.accept(self))
+    }
+
+    fn visit_super(&mut self, _keyword: &Token<'a>, method: &Token<'a>) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    fn visit_this(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("this")
+    }
+
+    fn visit_unary(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        let right = right.accept(self);
+        // `- -x` isn't `--x`, which would scan as a decrement
+        if operator.token_type == TokenType::MINUS && right.starts_with('-') {
+            format!("- {right}")
+        } else {
+            format!("{}{right}", operator.lexeme)
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) -> String {
+        name.lexeme.to_string()
+    }
+}
+
+impl<'a> StmtVisitor<'a, String> for Formatter {
+    fn visit_block(&mut self, statements: &[Stmt<'a>]) -> String {
+        match statements {
+            [initializer, Spanned {
+                node:
+                    StmtKind::While {
+                        condition,
+                        body,
+                        increment,
+                        syntax: LoopSyntax::For { initializer: true },
+                    },
+                ..
+            }] => {
+                let initializer = initializer.accept(self);
+                self.for_clauses(&initializer, condition, body, increment.as_ref())
+            }
+            _ => self.block(statements),
+        }
+    }
+
+    fn visit_break(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("break;")
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token<'a>,
+        superclass: Option<&Expr<'a>>,
+        methods: &[Function<'a>],
+    ) -> String {
+        let mut out = format!("class {}", name.lexeme);
+        if let Some(superclass) = superclass {
+            out.push_str(&format!(" < {}", superclass.accept(self)));
+        }
+        if methods.is_empty() {
+            out.push_str(" {}");
+            return out;
+        }
+        out.push_str(" {\n");
+        self.indent += 1;
+        for method in methods {
+            let prefix = if method.is_static { "class " } else { "" };
+            let method = if method.is_getter {
+                format!("{} {}", method.name.lexeme, self.block(&method.body))
+            } else {
+                self.function(&method.name.lexeme, &method.params, &method.body)
+            };
+            out.push_str(&format!("{}{prefix}{method}\n", self.pad()));
+        }
+        self.indent -= 1;
+        out.push_str(&format!("{}}}", self.pad()));
+        out
+    }
+
+    fn visit_continue(&mut self, _keyword: &Token<'a>) -> String {
+        String::from("continue;")
+    }
+
+    fn visit_expression(&mut self, expression: &Expr<'a>) -> String {
+        format!("{};", expression.accept(self))
+    }
+
+    fn visit_function(&mut self, function: &Function<'a>) -> String {
+        self.function(
+            &format!("fun {}", function.name.lexeme),
+            &function.params,
+            &function.body,
+        )
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr<'a>,
+        then_branch: &Stmt<'a>,
+        else_branch: Option<&Stmt<'a>>,
+    ) -> String {
+        let then_branch = self.body(then_branch);
+        let mut out = format!("if ({}){then_branch}", condition.accept(self));
+        if let Some(else_branch) = else_branch {
+            // after a closing brace on the same line, otherwise under the `if`
+            if then_branch.starts_with(' ') {
+                out.push_str(" else");
+            } else {
+                out.push_str(&format!("\n{}else", self.pad()));
+            }
+            match else_branch.node {
+                StmtKind::If { .. } => out.push_str(&format!(" {}", else_branch.accept(self))),
+                _ => out.push_str(&self.body(else_branch)),
+            }
+        }
+        out
+    }
+
+    fn visit_print(&mut self, expression: &Expr<'a>) -> String {
+        format!("print {};", expression.accept(self))
+    }
+
+    fn visit_return(&mut self, _keyword: &Token<'a>, value: Option<&Expr<'a>>) -> String {
+        match value {
+            Some(value) => format!("return {};", value.accept(self)),
+            None => String::from("return;"),
+        }
+    }
+
+    fn visit_var(&mut self, name: &Token<'a>, initializer: Option<&Expr<'a>>) -> String {
+        match initializer {
+            Some(initializer) => format!("var {} = {};", name.lexeme, initializer.accept(self)),
+            None => format!("var {};", name.lexeme),
+        }
+    }
+
+    fn visit_while(
+        &mut self,
+        condition: &Expr<'a>,
+        body: &Stmt<'a>,
+        increment: Option<&Expr<'a>>,
+        syntax: LoopSyntax,
+    ) -> String {
+        match syntax {
+            LoopSyntax::While => format!("while ({}){}", condition.accept(self), self.body(body)),
+            LoopSyntax::For { .. } => self.for_clauses(";", condition, body, increment),
+        }
+    }
+}
+
+// whether a block is the one a `for` with an initializer was desugared into
+fn desugared_for(statements: &[Stmt]) -> bool {
+    matches!(
+        statements,
+        [_, while_loop] if matches!(
+            while_loop.node,
+            StmtKind::While {
+                syntax: LoopSyntax::For { initializer: true },
+                ..
+            }
+        )
+    )
+}
+
+// A string's value quoted again, escaped so that it scans back the same.
+fn escape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            // would start an interpolation
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            _ if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// The statements as one JSON document, `{"statements": [...]}`, shaped as
+// serde would derive it: a variant with fields is `{"Name": {fields}}`, one
+// with a single value `{"Name": value}` and one with none `"Name"`; options
+// are the value or null, tuples arrays. Each expression and statement has
+// its span beside its variant, `{"Print": ..., "span": {"start": ..., "end":
+// ...}}`, and tokens are written out in full with their type, lexeme,
+// literal, line, column and span.
+pub fn program_json(statements: &[Stmt]) -> String {
+    let statements: Vec<_> = statements
+        .iter()
+        .map(|statement| JsonPrinter.stmt(statement))
+        .collect();
+    format!("{{\"statements\":{}}}", array(statements))
+}
+
+struct JsonPrinter;
+
+impl JsonPrinter {
+    fn expr(&mut self, expr: &Expr) -> String {
+        spanned(expr.accept(self), &expr.span)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        spanned(stmt.accept(self), &stmt.span)
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> String {
+        array(exprs.iter().map(|expr| self.expr(expr)).collect())
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> String {
+        array(stmts.iter().map(|stmt| self.stmt(stmt)).collect())
+    }
+
+    fn option_expr(&mut self, expr: Option<&Expr>) -> String {
+        expr.map_or_else(|| String::from("null"), |expr| self.expr(expr))
+    }
+
+    fn function(&mut self, function: &Function) -> String {
+        record(&[
+            ("name", token(&function.name)),
+            ("params", tokens(&function.params)),
+            ("body", self.stmts(&function.body)),
+            ("is_static", function.is_static.to_string()),
+            ("is_getter", function.is_getter.to_string()),
+        ])
+    }
+}
+
+impl<'a> ExprVisitor<'a, String> for JsonPrinter {
+    fn visit_array_literal(&mut self, elements: &[Expr<'a>]) -> String {
+        variant("ArrayLiteral", self.exprs(elements))
+    }
+
+    fn visit_assign(&mut self, name: &Token<'a>, value: &Expr<'a>) -> String {
+        let fields = record(&[("name", token(name)), ("value", self.expr(value))]);
+        variant("Assign", fields)
+    }
+
+    fn visit_binary(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        let fields = record(&[
+            ("left", self.expr(left)),
+            ("operator", token(operator)),
+            ("right", self.expr(right)),
+        ]);
+        variant("Binary", fields)
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr<'a>,
+        paren: &Token<'a>,
+        arguments: &[Expr<'a>],
+    ) -> String {
+        let fields = record(&[
+            ("callee", self.expr(callee)),
+            ("paren", token(paren)),
+            ("arguments", self.exprs(arguments)),
+        ]);
+        variant("Call", fields)
+    }
+
+    fn visit_get(&mut self, object: &Expr<'a>, name: &Token<'a>) -> String {
+        let fields = record(&[("object", self.expr(object)), ("name", token(name))]);
+        variant("Get", fields)
+    }
+
+    fn visit_index(&mut self, object: &Expr<'a>, bracket: &Token<'a>, index: &Expr<'a>) -> String {
+        let fields = record(&[
+            ("object", self.expr(object)),
+            ("bracket", token(bracket)),
+            ("index", self.expr(index)),
+        ]);
+        variant("Index", fields)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Expr<'a>,
+        bracket: &Token<'a>,
+        index: &Expr<'a>,
+        value: &Expr<'a>,
+    ) -> String {
+        let fields = record(&[
+            ("object", self.expr(object)),
+            ("bracket", token(bracket)),
+            ("index", self.expr(index)),
+            ("value", self.expr(value)),
+        ]);
+        variant("IndexSet", fields)
+    }
+
+    fn visit_grouping(&mut self, expression: &Expr<'a>) -> String {
+        variant("Grouping", self.expr(expression))
+    }
+
+    fn visit_interpolation(&mut self, parts: &[Expr<'a>]) -> String {
+        variant("Interpolation", self.exprs(parts))
+    }
+
+    fn visit_lambda(
+        &mut self,
+        keyword: &Token<'a>,
+        params: &[Token<'a>],
+        body: &[Stmt<'a>],
+    ) -> String {
+        let fields = record(&[
+            ("keyword", token(keyword)),
+            ("params", tokens(params)),
+            ("body", self.stmts(body)),
+        ]);
+        variant("Lambda", fields)
+    }
+
+    fn visit_literal(&mut self, value: &LiteralValue<'a>) -> String {
+        let value = match value {
+            LiteralValue::Number(n) => variant("Number", number(*n)),
+            LiteralValue::Str(s) => variant("Str", string(s)),
+            LiteralValue::Bool(b) => variant("Bool", b.to_string()),
+            LiteralValue::Nil => string("Nil"),
+        };
+        variant("Literal", value)
+    }
+
+    fn visit_map_literal(&mut self, entries: &[(Expr<'a>, Expr<'a>)]) -> String {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| array(vec![self.expr(key), self.expr(value)]))
+            .collect();
+        variant("MapLiteral", array(entries))
+    }
+
+    fn visit_logical(&mut self, left: &Expr<'a>, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        let fields = record(&[
+            ("left", self.expr(left)),
+            ("operator", token(operator)),
+            ("right", self.expr(right)),
+        ]);
+        variant("Logical", fields)
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expr<'a>,
+        then_expr: &Expr<'a>,
+        else_expr: &Expr<'a>,
+    ) -> String {
+        let fields = record(&[
+            ("condition", self.expr(condition)),
+            ("then_expr", self.expr(then_expr)),
+            ("else_expr", self.expr(else_expr)),
+        ]);
+        variant("Ternary", fields)
+    }
+
+    fn visit_set(&mut self, object: &Expr<'a>, name: &Token<'a>, value: &Expr<'a>) -> String {
+        let fields = record(&[
+            ("object", self.expr(object)),
+            ("name", token(name)),
+            ("value", self.expr(value)),
+        ]);
+        variant("Set", fields)
+    }
+
+    fn visit_super(&mut self, keyword: &Token<'a>, method: &Token<'a>) -> String {
+        let fields = record(&[("keyword", token(keyword)), ("method", token(method))]);
+        variant("Super", fields)
+    }
+
+    fn visit_this(&mut self, keyword: &Token<'a>) -> String {
+        variant("This", token(keyword))
+    }
+
+    fn visit_unary(&mut self, operator: &Token<'a>, right: &Expr<'a>) -> String {
+        let fields = record(&[("operator", token(operator)), ("right", self.expr(right))]);
+        variant("Unary", fields)
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) -> String {
+        variant("Variable", token(name))
+    }
+}
+
+impl<'a> StmtVisitor<'a, String> for JsonPrinter {
+    fn visit_block(&mut self, statements: &[Stmt<'a>]) -> String {
+        variant("Block", self.stmts(statements))
+    }
+
+    fn visit_break(&mut self, keyword: &Token<'a>) -> String {
+        variant("Break", token(keyword))
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token<'a>,
+        superclass: Option<&Expr<'a>>,
+        methods: &[Function<'a>],
+    ) -> String {
+        let methods = methods.iter().map(|method| self.function(method)).collect();
+        let fields = record(&[
+            ("name", token(name)),
+            ("superclass", self.option_expr(superclass)),
+            ("methods", array(methods)),
+        ]);
+        variant("Class", fields)
+    }
+
+    fn visit_continue(&mut self, keyword: &Token<'a>) -> String {
+        variant("Continue", token(keyword))
+    }
+
+    fn visit_expression(&mut self, expression: &Expr<'a>) -> String {
+        variant("Expression", self.expr(expression))
+    }
+
+    fn visit_function(&mut self, function: &Function<'a>) -> String {
+        variant("Function", self.function(function))
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr<'a>,
+        then_branch: &Stmt<'a>,
+        else_branch: Option<&Stmt<'a>>,
+    ) -> String {
+        let else_branch = else_branch.map_or_else(|| String::from("null"), |stmt| self.stmt(stmt));
+        let fields = record(&[
+            ("condition", self.expr(condition)),
+            ("then_branch", self.stmt(then_branch)),
+            ("else_branch", else_branch),
+        ]);
+        variant("If", fields)
+    }
+
+    fn visit_print(&mut self, expression: &Expr<'a>) -> String {
+        variant("Print", self.expr(expression))
+    }
+
+    fn visit_return(&mut self, keyword: &Token<'a>, value: Option<&Expr<'a>>) -> String {
+        let fields = record(&[
+            ("keyword", token(keyword)),
+            ("value", self.option_expr(value)),
+        ]);
+        variant("Return", fields)
+    }
+
+    fn visit_var(&mut self, name: &Token<'a>, initializer: Option<&Expr<'a>>) -> String {
+        let fields = record(&[
+            ("name", token(name)),
+            ("initializer", self.option_expr(initializer)),
+        ]);
+        variant("Var", fields)
+    }
+
+    fn visit_while(
+        &mut self,
+        condition: &Expr<'a>,
+        body: &Stmt<'a>,
+        increment: Option<&Expr<'a>>,
+        syntax: LoopSyntax,
+    ) -> String {
+        let syntax = match syntax {
+            LoopSyntax::While => string("While"),
+            LoopSyntax::For { initializer } => {
+                variant("For", record(&[("initializer", initializer.to_string())]))
+            }
+        };
+        let fields = record(&[
+            ("condition", self.expr(condition)),
+            ("body", self.stmt(body)),
+            ("increment", self.option_expr(increment)),
+            ("syntax", syntax),
+        ]);
+        variant("While", fields)
+    }
+}
+
+fn token(token: &Token) -> String {
+    let literal = match &token.literal {
+        Some(Literal::Number(n)) => variant("Number", number(*n)),
+        Some(Literal::Str(s)) => variant("Str", string(s)),
+        None => String::from("null"),
+    };
+    record(&[
+        ("token_type", string(&token.token_type.to_string())),
+        ("lexeme", string(&token.lexeme)),
+        ("literal", literal),
+        ("line", token.line.to_string()),
+        ("column", token.column.to_string()),
+        ("span", range(&token.span)),
+    ])
+}
+
+fn range(range: &Range<usize>) -> String {
+    record(&[
+        ("start", range.start.to_string()),
+        ("end", range.end.to_string()),
+    ])
+}
+
+// The node is a variant, `{"Name":value}`, which the span goes beside.
+fn spanned(node: String, span: &Range<usize>) -> String {
+    let variant = node
+        .strip_prefix('{')
+        .and_then(|node| node.strip_suffix('}'))
+        .expect("a node printed as a variant");
+    format!("{{{variant},\"span\":{}}}", range(span))
+}
+
+fn tokens(tokens: &[Token]) -> String {
+    array(tokens.iter().map(token).collect())
+}
+
+fn variant(name: &str, value: String) -> String {
+    format!("{{\"{name}\":{value}}}")
+}
+
+fn record(fields: &[(&str, String)]) -> String {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|(name, value)| format!("\"{name}\":{value}"))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn array(values: Vec<String>) -> String {
+    format!("[{}]", values.join(","))
+}
+
+// JSON has no infinities, so as serde_json does they come out as null
+fn number(n: f64) -> String {
+    if n.is_finite() {
+        format!("{n}")
+    } else {
+        String::from("null")
+    }
+}
+
+fn string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{ExprKind, Spanned},
+        parser::Parser,
+        Scanner, ScannerOptions,
+    };
+    use std::borrow::Cow;
+
+    // a token on line 1 at `span`
+    fn token(token_type: TokenType, lexeme: &str, span: Range<usize>) -> Token<'_> {
+        Token {
+            token_type,
+            lexeme: Cow::Borrowed(lexeme),
+            literal: None,
+            line: 1,
+            column: span.start + 1,
+            span,
+        }
+    }
+
+    fn number(n: f64, span: Range<usize>) -> Expr<'static> {
+        Spanned::new(ExprKind::Literal(LiteralValue::Number(n)), span)
+    }
+
+    #[test]
+    fn trees_built_by_hand() {
+        // -123 * (45.67)
+        let negated = Expr::unary(token(TokenType::MINUS, "-", 0..1), number(123.0, 1..4));
+        let grouped = Spanned::new(ExprKind::Grouping(Box::new(number(45.67, 8..13))), 7..14);
+        let product = Expr::binary(negated, token(TokenType::STAR, "*", 5..6), grouped);
+        assert_eq!(product.span, 0..14);
+        let ExprKind::Binary { left, right, .. } = &product.node else {
+            panic!("not a binary: {product:?}");
+        };
+        assert!(matches!(left.node, ExprKind::Unary { .. }));
+        assert!(matches!(right.node, ExprKind::Grouping(_)));
+        assert_eq!(AstPrinter.print(&product), "(* (- 123) (group 45.67))");
+    }
+
+    fn parse(source: &str) -> Vec<Stmt<'_>> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        statements
+    }
+
+    // the expression of the one statement in `source`, an expression statement
+    fn expression(source: &str) -> Expr<'_> {
+        match parse(source).remove(0).node {
+            StmtKind::Expression(expr) => expr,
+            statement => panic!("not an expression statement: {statement:?}"),
+        }
+    }
+
+    #[test]
+    fn parenthesized() {
+        let printed = |source| AstPrinter.print(&expression(source));
+        assert_eq!(printed("-123 * (45.67);"), "(* (- 123) (group 45.67))");
+        assert_eq!(printed("!!-x;"), "(! (! (- x)))");
+        assert_eq!(printed("- -1.50 + 1e3;"), "(+ (- (- 1.5)) 1000)");
+        assert_eq!(
+            printed("\"a\" == nil or true and false;"),
+            "(or (== \"a\" nil) (and true false))"
+        );
+        assert_eq!(printed("a = b ? c : d;"), "(= a (?: b c d))");
+        assert_eq!(
+            AstPrinter.print_stmt(&parse("print f(1)[2].x;").remove(0)),
+            "(print (. (index (call f 1) 2) x))"
+        );
+    }
+
+    #[test]
+    fn reverse_polish() {
+        let printed = |source| RpnPrinter.print(&expression(source));
+        assert_eq!(printed("(1 + 2) * (4 - 3);"), "1 2 + 4 3 - *");
+        assert_eq!(printed("-a - -(b);"), "a neg b neg -");
+        assert_eq!(printed("!((x));"), "x !");
+        assert_eq!(printed("f(1, g(2)).y;"), "f 1 g 2 call/1 call/2 y .");
+        assert_eq!(printed("[1, 2][0];"), "1 2 array/2 0 []");
+    }
+
+    // A pass of its own, counting literals.
+    struct LiteralCounter;
+
+    impl<'a> ExprVisitor<'a, usize> for LiteralCounter {
+        fn visit_array_literal(&mut self, elements: &[Expr<'a>]) -> usize {
+            elements.iter().map(|element| element.accept(self)).sum()
+        }
+        fn visit_assign(&mut self, _: &Token<'a>, value: &Expr<'a>) -> usize {
+            value.accept(self)
+        }
+        fn visit_binary(&mut self, left: &Expr<'a>, _: &Token<'a>, right: &Expr<'a>) -> usize {
+            left.accept(self) + right.accept(self)
+        }
+        fn visit_call(
+            &mut self,
+            callee: &Expr<'a>,
+            _: &Token<'a>,
+            arguments: &[Expr<'a>],
+        ) -> usize {
+            callee.accept(self) + self.visit_array_literal(arguments)
+        }
+        fn visit_get(&mut self, object: &Expr<'a>, _: &Token<'a>) -> usize {
+            object.accept(self)
+        }
+        fn visit_grouping(&mut self, expression: &Expr<'a>) -> usize {
+            expression.accept(self)
+        }
+        fn visit_index(&mut self, object: &Expr<'a>, _: &Token<'a>, index: &Expr<'a>) -> usize {
+            object.accept(self) + index.accept(self)
+        }
+        fn visit_index_set(
+            &mut self,
+            object: &Expr<'a>,
+            _: &Token<'a>,
+            index: &Expr<'a>,
+            value: &Expr<'a>,
+        ) -> usize {
+            object.accept(self) + index.accept(self) + value.accept(self)
+        }
+        fn visit_interpolation(&mut self, parts: &[Expr<'a>]) -> usize {
+            self.visit_array_literal(parts)
+        }
+        // statements are another pass's business
+        fn visit_lambda(&mut self, _: &Token<'a>, _: &[Token<'a>], _: &[Stmt<'a>]) -> usize {
+            0
+        }
+        fn visit_literal(&mut self, _: &LiteralValue<'a>) -> usize {
+            1
+        }
+        fn visit_map_literal(&mut self, entries: &[(Expr<'a>, Expr<'a>)]) -> usize {
+            entries
+                .iter()
+                .map(|(key, value)| key.accept(self) + value.accept(self))
+                .sum()
+        }
+        fn visit_logical(&mut self, left: &Expr<'a>, _: &Token<'a>, right: &Expr<'a>) -> usize {
+            left.accept(self) + right.accept(self)
+        }
+        fn visit_ternary(
+            &mut self,
+            condition: &Expr<'a>,
+            then_expr: &Expr<'a>,
+            else_expr: &Expr<'a>,
+        ) -> usize {
+            condition.accept(self) + then_expr.accept(self) + else_expr.accept(self)
+        }
+        fn visit_set(&mut self, object: &Expr<'a>, _: &Token<'a>, value: &Expr<'a>) -> usize {
+            object.accept(self) + value.accept(self)
+        }
+        fn visit_super(&mut self, _: &Token<'a>, _: &Token<'a>) -> usize {
+            0
+        }
+        fn visit_this(&mut self, _: &Token<'a>) -> usize {
+            0
+        }
+        fn visit_unary(&mut self, _: &Token<'a>, right: &Expr<'a>) -> usize {
+            right.accept(self)
+        }
+        fn visit_variable(&mut self, _: &Token<'a>) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn passes_written_as_visitors() {
+        let count = |source| expression(source).accept(&mut LiteralCounter);
+        assert_eq!(count("x;"), 0);
+        assert_eq!(count("1 + -(2 * x) == \"three\";"), 3);
+        assert_eq!(
+            count("f(nil, [true, {1: 2}]) ? a.b = 3 : fun () { 4; };"),
+            5
+        );
+    }
+
+    // Just enough of a JSON reader to check the document parses and to look
+    // things up in it; strings aren't unescaped.
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        Str(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    impl Json {
+        fn parse(text: &str) -> Json {
+            let mut rest = text.trim_start();
+            let value = Json::value(&mut rest);
+            assert!(rest.trim().is_empty(), "trailing {rest:?}");
+            value
+        }
+
+        fn value(rest: &mut &str) -> Json {
+            let value = match rest.chars().next() {
+                Some('{') => {
+                    *rest = &rest[1..];
+                    let mut fields = Vec::new();
+                    while !Json::eat(rest, '}') {
+                        Json::eat(rest, ',');
+                        let Json::Str(name) = Json::value(rest) else {
+                            panic!("a key that isn't a string at {rest:?}");
+                        };
+                        assert!(Json::eat(rest, ':'), "no colon at {rest:?}");
+                        fields.push((name, Json::value(rest)));
+                    }
+                    Json::Object(fields)
+                }
+                Some('[') => {
+                    *rest = &rest[1..];
+                    let mut values = Vec::new();
+                    while !Json::eat(rest, ']') {
+                        Json::eat(rest, ',');
+                        values.push(Json::value(rest));
+                    }
+                    Json::Array(values)
+                }
+                Some('"') => {
+                    let mut escaped = false;
+                    let end = rest[1..]
+                        .find(|c| {
+                            let end = c == '"' && !escaped;
+                            escaped = c == '\\' && !escaped;
+                            end
+                        })
+                        .expect("an unterminated string")
+                        + 1;
+                    let value = Json::Str(String::from(&rest[1..end]));
+                    *rest = &rest[end + 1..];
+                    value
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| matches!(c, ',' | '}' | ']') || c.is_whitespace())
+                        .unwrap_or(rest.len());
+                    let value = match &rest[..end] {
+                        "null" => Json::Null,
+                        "true" => Json::Bool(true),
+                        "false" => Json::Bool(false),
+                        n => Json::Number(n.parse().expect("a number")),
+                    };
+                    *rest = &rest[end..];
+                    value
+                }
+            };
+            *rest = rest.trim_start();
+            value
+        }
+
+        fn eat(rest: &mut &str, c: char) -> bool {
+            let eaten = rest.starts_with(c);
+            if eaten {
+                *rest = rest[1..].trim_start();
+            }
+            eaten
+        }
+
+        // follows a path of keys and indices, like `["statements", "0"]`
+        fn at(&self, path: &[&str]) -> &Json {
+            path.iter().fold(self, |json, step| match json {
+                Json::Object(fields) => fields
+                    .iter()
+                    .find(|(name, _)| name == step)
+                    .map(|(_, value)| value)
+                    .unwrap_or_else(|| panic!("no {step} in {json:?}")),
+                Json::Array(values) => &values[step.parse::<usize>().unwrap()],
+                json => panic!("no {step} in {json:?}"),
+            })
+        }
+    }
+
+    #[test]
+    fn program_as_json() {
+        let json = program_json(&parse("var a = \"x\";\nprint a + 1;"));
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"statements":["#,
+                r#"{"Var":{"#,
+                r#""name":{"token_type":"IDENTIFIER","lexeme":"a","literal":null,"#,
+                r#""line":1,"column":5,"span":{"start":4,"end":5}},"#,
+                r#""initializer":{"Literal":{"Str":"x"},"span":{"start":8,"end":11}}"#,
+                r#"},"span":{"start":0,"end":12}},"#,
+                r#"{"Print":{"Binary":{"#,
+                r#""left":{"Variable":{"token_type":"IDENTIFIER","lexeme":"a","#,
+                r#""literal":null,"line":2,"column":7,"span":{"start":19,"end":20}},"#,
+                r#""span":{"start":19,"end":20}},"#,
+                r#""operator":{"token_type":"PLUS","lexeme":"+","literal":null,"#,
+                r#""line":2,"column":9,"span":{"start":21,"end":22}},"#,
+                r#""right":{"Literal":{"Number":1},"span":{"start":23,"end":24}}"#,
+                r#"},"span":{"start":19,"end":24}},"span":{"start":13,"end":25}}"#,
+                r#"]}"#,
+            )
+        );
+        let json = Json::parse(&json);
+        let name = json.at(&["statements", "0", "Var", "name"]);
+        assert_eq!(name.at(&["lexeme"]), &Json::Str(String::from("a")));
+        assert_eq!(name.at(&["line"]), &Json::Number(1.0));
+        let sum = json.at(&["statements", "1", "Print", "Binary"]);
+        assert_eq!(sum.at(&["right", "Literal", "Number"]), &Json::Number(1.0));
+        assert_eq!(
+            sum.at(&["operator", "token_type"]),
+            &Json::Str(String::from("PLUS"))
+        );
+    }
+
+    #[test]
+    fn interpolations_print_as_written() {
+        let source = "print \"sum is ${a + b}\\n\";\nprint \"${a}\";\nprint \"$${\"in ${x}\"}\";\n";
+        let tokens = Scanner::with_options(source, ScannerOptions::default().interpolation(true))
+            .scan_tokens()
+            .unwrap();
+        let (statements, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(format_program(&statements), source);
+        let StmtKind::Print(sum) = &statements[0].node else {
+            panic!("not a print: {:?}", statements[0]);
+        };
+        assert_eq!(AstPrinter.print(sum), "\"sum is ${(+ a b)}\n\"");
+        assert_eq!(RpnPrinter.print(sum), "\"sum is ${a b +}\n\"");
+        let ExprKind::Interpolation(parts) = &sum.node else {
+            panic!("not an interpolation: {sum:?}");
+        };
+        assert_eq!(parts.len(), 3);
+        assert_eq!(&source[sum.span.clone()], "\"sum is ${a + b}\\n\"");
+    }
+
+    #[test]
+    fn only_for_loops_format_as_for() {
+        let format = |source| format_program(&parse(source));
+        assert_eq!(
+            format("for (var i = 0; i < 3; i = i + 1) print i;"),
+            "for (var i = 0; i < 3; i = i + 1)\n    print i;\n"
+        );
+        assert_eq!(format("for (;;) {}"), "for (;;) {}\n");
+        assert_eq!(format("for (i = 0;;) {}"), "for (i = 0;;) {}\n");
+        // the same trees, but as written, aren't made into a for
+        assert_eq!(
+            format("{ var i = 0; while (i < 3) print i; }"),
+            "{\n    var i = 0;\n    while (i < 3)\n        print i;\n}\n"
+        );
+        assert_eq!(
+            format("{ var i = 0; for (; i < 3;) {} }"),
+            "{\n    var i = 0;\n    for (; i < 3;) {}\n}\n"
+        );
+    }
+}
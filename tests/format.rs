@@ -0,0 +1,42 @@
+use craftinginterpreters::{
+    ast::Stmt,
+    parser::Parser,
+    printer::{format_program, AstPrinter},
+    Scanner,
+};
+use std::{fs, path::Path};
+
+fn parse(source: &str) -> Vec<Stmt<'_>> {
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let (statements, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty(), "{errors:?}\n{source}");
+    statements
+}
+
+// the tree as AstPrinter shows it, which leaves out lines and spans
+fn tree(statements: &[Stmt]) -> Vec<String> {
+    statements
+        .iter()
+        .map(|statement| AstPrinter.print_stmt(statement))
+        .collect()
+}
+
+// Each program in tests/programs, formatted, parses to the tree it was
+// formatted from, and formats the same again.
+#[test]
+fn formatted_programs_parse_to_the_same_tree() {
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut checked = 0;
+    for entry in fs::read_dir(programs).unwrap() {
+        let path = entry.unwrap().path();
+        println!("{}", path.display());
+        let source = fs::read_to_string(&path).unwrap();
+        let statements = parse(&source);
+        let formatted = format_program(&statements);
+        let reparsed = parse(&formatted);
+        assert_eq!(tree(&reparsed), tree(&statements));
+        assert_eq!(format_program(&reparsed), formatted);
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
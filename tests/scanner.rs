@@ -0,0 +1,33 @@
+mod support;
+
+use std::{fs, path::Path};
+use support::{check, generate};
+
+// The fuzz targets' checks over every source in tests/corpus, so they run
+// under plain `cargo test`. Inputs the fuzzer finds crashing belong there.
+#[test]
+fn corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut sources = 0;
+    for entry in fs::read_dir(corpus).unwrap() {
+        let path = entry.unwrap().path();
+        // shown if the checks fail
+        println!("{}", path.display());
+        // as the scan_bytes target sees its input
+        check::scan(&String::from_utf8_lossy(&fs::read(&path).unwrap()));
+        sources += 1;
+    }
+    assert!(sources > 0);
+}
+
+#[test]
+fn tokens_reconstruct_generated_sources() {
+    let mut rng = generate::Rng::new(0x10C5);
+    let mut checked = 0;
+    for _ in 0..2000 {
+        let source = generate::source(&mut rng, 40);
+        checked += check::reconstruct(&source) as usize;
+    }
+    // the property says nothing about sources that fail to scan
+    assert!(checked > 1000, "only {checked} sources scanned");
+}
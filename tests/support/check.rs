@@ -0,0 +1,99 @@
+use craftinginterpreters::{LoxError, Scanner, ScannerLimits, ScannerOptions, Token, TokenType};
+use std::io::{BufReader, Cursor};
+
+// Scanning must not panic, must end with EOF when it succeeds, and must give
+// the same results whether the source is in memory or streamed in chunks.
+pub fn scan(source: &str) {
+    let strict = ScannerOptions::default()
+        .comment_tokens(true)
+        .doc_comments(true)
+        .strict_newlines(true);
+    let extended = ScannerOptions::default()
+        .hex_literals(true)
+        .single_quotes(true)
+        .raw_strings(true)
+        .interpolation(true)
+        .unicode_identifiers(true)
+        .in_keyword(true)
+        .const_keyword(true);
+    let limits = ScannerLimits {
+        max_source_bytes: Some(source.len() / 2),
+        max_tokens: Some(8),
+        max_string_length: Some(8),
+        max_number_length: Some(4),
+    };
+    for options in [ScannerOptions::default(), strict, extended] {
+        if let Ok(tokens) = Scanner::with_options(source, options.clone()).scan_tokens() {
+            assert_eq!(
+                tokens.last().map(|token| token.token_type),
+                Some(TokenType::EOF)
+            );
+        }
+        for limits in [ScannerLimits::default(), limits] {
+            let in_memory: Vec<_> = Scanner::with_limits(source, options.clone(), limits)
+                .map(describe)
+                .collect();
+            let reader = BufReader::with_capacity(3, Cursor::new(source.as_bytes()));
+            let streamed: Vec<_> =
+                Scanner::from_reader_with_limits(reader, options.clone(), limits)
+                    .map(describe)
+                    .collect();
+            assert_eq!(in_memory, streamed);
+        }
+    }
+    reconstruct(source);
+}
+
+// Each token's lexeme is its span of the source, and what lies between tokens
+// is whitespace, or the byte order mark and "#!" line at the start. Pasting
+// the gaps and lexemes back together therefore gives the source back. Returns
+// whether there were tokens to check, which there aren't after an error.
+pub fn reconstruct(source: &str) -> bool {
+    let options = ScannerOptions::default()
+        .comment_tokens(true)
+        .interpolation(true);
+    let Ok(tokens) = Scanner::with_options(source, options).scan_tokens() else {
+        return false;
+    };
+    let mut rebuilt = String::new();
+    for token in &tokens {
+        let gap = &source[rebuilt.len()..token.span.start];
+        let trivia = if rebuilt.is_empty() {
+            let gap = gap.strip_prefix('\u{feff}').unwrap_or(gap);
+            match gap.strip_prefix("#!") {
+                Some(shebang) => shebang.trim_start_matches(|c| c != '\n'),
+                None => gap,
+            }
+        } else {
+            gap
+        };
+        assert!(trivia
+            .chars()
+            .all(|c| matches!(c, ' ' | '\r' | '\t' | '\n' | '\x0B' | '\x0C')));
+        assert_eq!(token.lexeme, &source[token.span.clone()]);
+        rebuilt.push_str(gap);
+        rebuilt.push_str(&token.lexeme);
+    }
+    assert_eq!(rebuilt, source);
+    true
+}
+
+fn describe(result: Result<Token, LoxError>) -> String {
+    match result {
+        Ok(token) => format!("{token:?}"),
+        Err(
+            LoxError::ParseError {
+                line,
+                column,
+                message,
+            }
+            | LoxError::Warning {
+                line,
+                column,
+                message,
+            },
+        ) => format!("[line {line}:{column}] {message}"),
+        Err(LoxError::SyntaxError { token, message, .. }) => format!("{token:?}: {message}"),
+        Err(LoxError::IoError { message }) => message,
+    }
+}
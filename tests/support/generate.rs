@@ -0,0 +1,62 @@
+// Random Lox-ish sources built from a small grammar of tokens and trivia.
+// Most of them scan cleanly; some don't, where pieces run together.
+
+const NAMES: &[&str] = &[
+    "a", "_b", "counter", "x1", "and", "class", "fun", "nil", "this",
+];
+const NUMBERS: &[&str] = &["0", "7", "12.5", "1e3", "2.5E-3", ".25", "1_000"];
+const STRINGS: &[&str] = &[
+    r#""""#,
+    r#""plain""#,
+    r#""tab\tquote\"""#,
+    r#""\u{e9}""#,
+    r#""héllo 日本""#,
+    "\"two\nlines\"",
+    r#""sum ${a + 1} end""#,
+    r#""${"nested ${b}"}""#,
+];
+const OPERATORS: &[&str] = &[
+    "(", ")", "{", "}", "[", "]", ",", ".", ";", "-", "+", "*", "/", "%", "!", "!=", "=", "==",
+    ">", ">=", "<", "<=", "?", ":", "->", "++", "--", "+=", "<<", ">>", "&", "|", "^", "~",
+];
+const COMMENTS: &[&str] = &[
+    "// line\n",
+    "/// doc\n",
+    "/* block */",
+    "/* a /* nested */ b */",
+];
+const WHITESPACE: &[&str] = &[" ", "  ", "\t", "\n", "\r\n"];
+
+// xorshift64*, seeded so a failure can be reproduced
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as usize % n
+    }
+
+    pub fn pick(&mut self, items: &[&'static str]) -> &'static str {
+        items[self.below(items.len())]
+    }
+}
+
+// `pieces` tokens or comments, usually with whitespace between them
+pub fn source(rng: &mut Rng, pieces: usize) -> String {
+    let mut source = String::new();
+    let kinds = [NAMES, NUMBERS, STRINGS, OPERATORS, COMMENTS];
+    for _ in 0..pieces {
+        let kind = kinds[rng.below(kinds.len())];
+        source.push_str(rng.pick(kind));
+        if rng.below(4) != 0 {
+            source.push_str(rng.pick(WHITESPACE));
+        }
+    }
+    source
+}
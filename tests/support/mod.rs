@@ -0,0 +1,3 @@
+// Shared by the integration tests and, through #[path], the fuzz targets.
+pub mod check;
+pub mod generate;